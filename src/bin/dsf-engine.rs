@@ -0,0 +1,304 @@
+//! Command line tool for running and debugging DSF engines
+
+use std::io::BufRead;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use log::{debug, info};
+use simplelog::{LevelFilter, SimpleLogger};
+use structopt::StructOpt;
+
+use dsf_core::{prelude::*, api::Application, wire::Container};
+
+use dsf_engine::{
+    comms::capture::CaptureReader,
+    engine::{Engine, EngineEvent, EngineOptions},
+    store::{SledStore, Store},
+};
+
+/// Generic application for CLI use, info and data are raw bytes
+pub struct Generic {}
+
+impl Application for Generic {
+    const APPLICATION_ID: u16 = 0x0102;
+
+    type Info = Vec<u8>;
+
+    type Data = Vec<u8>;
+
+    fn matches(info: &Self::Info, req: &[u8]) -> bool {
+        req.is_empty() || info == req
+    }
+}
+
+/// Engine page buffer size
+const N: usize = 512;
+
+type E = Engine<Generic, UdpSocket, SledStore<SocketAddr>, N>;
+
+#[derive(Clone, Debug, StructOpt)]
+#[structopt(name = "dsf-engine", about = "DSF engine command line tool")]
+struct Options {
+    /// Local address for the engine socket
+    #[structopt(long, default_value = "0.0.0.0:10100")]
+    bind: SocketAddr,
+
+    /// Directory for the engine store
+    #[structopt(long, default_value = "./dsf-engine.db")]
+    store: String,
+
+    /// Service information (hex encoded)
+    #[structopt(long, parse(try_from_str = parse_hex), default_value = "")]
+    info: Vec<u8>,
+
+    /// Log level
+    #[structopt(long, default_value = "info")]
+    log_level: LevelFilter,
+
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+enum Command {
+    /// Run the engine, printing events
+    Run,
+
+    /// Discover local services
+    Discover {
+        /// Discovery request body (hex encoded)
+        #[structopt(long, parse(try_from_str = parse_hex), default_value = "")]
+        body: Vec<u8>,
+
+        /// Time to wait for responses in milliseconds
+        #[structopt(long, default_value = "3000")]
+        timeout_ms: u64,
+    },
+
+    /// Subscribe to a service and print received data
+    Subscribe {
+        /// Service ID
+        #[structopt(long)]
+        id: Id,
+
+        /// Service address
+        #[structopt(long)]
+        addr: SocketAddr,
+    },
+
+    /// Publish lines from stdin as service data
+    Publish,
+
+    /// Print peers from the store
+    Peers,
+
+    /// Print pages from the store
+    Pages,
+
+    /// Print store contents, excluding private keys
+    Dump {
+        /// Output JSON
+        #[structopt(long)]
+        json: bool,
+    },
+
+    /// Export the full store to an archive file
+    Export {
+        /// Archive file
+        file: String,
+    },
+
+    /// Import the full store from an archive file
+    Import {
+        /// Archive file
+        file: String,
+    },
+
+    /// Decode and print a packet capture file
+    Decode {
+        /// Capture (pcap) file
+        file: String,
+    },
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    if s.len() % 2 != 0 {
+        return Err(format!("Invalid hex length: {}", s.len()));
+    }
+
+    (0..s.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i+2], 16).map_err(|e| e.to_string()))
+        .collect()
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opts = Options::from_args();
+
+    let _ = SimpleLogger::init(opts.log_level, Default::default());
+
+    debug!("Options: {:?}", opts);
+
+    // Inspection commands only require the store
+    match &opts.cmd {
+        Command::Peers => return print_peers(&opts),
+        Command::Pages => return print_pages(&opts),
+        Command::Dump{ json } => return dump(&opts, *json),
+        Command::Export{ file } => {
+            let store = SledStore::<SocketAddr>::new(&opts.store)?;
+            store.export(std::io::BufWriter::new(std::fs::File::create(file)?))?;
+            return Ok(());
+        },
+        Command::Import{ file } => {
+            let mut store = SledStore::<SocketAddr>::new(&opts.store)?;
+            store.import(std::io::BufReader::new(std::fs::File::open(file)?))?;
+            return Ok(());
+        },
+        Command::Decode{ file } => {
+            let r = CaptureReader::new(std::io::BufReader::new(std::fs::File::open(file)?))?;
+            for p in r {
+                println!("{}", p?);
+            }
+            return Ok(());
+        },
+        _ => (),
+    }
+
+    // Setup engine, collecting discovery responses for the requested timeout
+    let mut engine_opts = EngineOptions::default();
+    if let Command::Discover{ timeout_ms, .. } = &opts.cmd {
+        engine_opts.discover_window_ms = *timeout_ms;
+    }
+
+    let socket = UdpSocket::bind(opts.bind)?;
+    socket.set_broadcast(true)?;
+    socket.set_nonblocking(true)?;
+
+    let store = SledStore::<SocketAddr>::new(&opts.store)?;
+    let mut e = E::with_options(opts.info.clone(), engine_opts, socket, store)?;
+
+    info!("Started engine {} at {}", e.id(), e.addr()?);
+
+    match opts.cmd {
+        Command::Run => run(&mut e)?,
+        Command::Discover{ body, .. } => {
+            for d in e.discover_wait(&body, &[])? {
+                println!("Discovered: {} ({}) info: {:02x?}", d.id, d.addr, d.info);
+            }
+
+            // Print other events received while waiting
+            let events: Vec<_> = e.drain_events().collect();
+            for evt in &events {
+                print_event(&mut e, evt)?;
+            }
+        },
+        Command::Subscribe{ id, addr } => {
+            e.subscribe(id, addr)?;
+            run(&mut e)?;
+        },
+        Command::Publish => publish(&mut e)?,
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Poll the engine, printing events
+fn run(e: &mut E) -> Result<(), Box<dyn std::error::Error>> {
+    loop {
+        let evt = e.tick()?;
+        print_event(e, &evt)?;
+
+        if evt == EngineEvent::None {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// Publish lines from stdin while polling the engine
+fn publish(e: &mut E) -> Result<(), Box<dyn std::error::Error>> {
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        for l in std::io::stdin().lock().lines() {
+            match l {
+                Ok(l) if tx.send(l).is_ok() => (),
+                _ => break,
+            }
+        }
+    });
+
+    loop {
+        match rx.try_recv() {
+            Ok(l) => {
+                let sig = e.publish(l.into_bytes(), &[])?;
+                println!("Published: {}", sig);
+            },
+            Err(mpsc::TryRecvError::Disconnected) => return Ok(()),
+            Err(mpsc::TryRecvError::Empty) => (),
+        }
+
+        let evt = e.tick()?;
+        print_event(e, &evt)?;
+
+        if evt == EngineEvent::None {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+fn print_event(e: &mut E, evt: &EngineEvent) -> Result<(), Box<dyn std::error::Error>> {
+    match evt {
+        EngineEvent::None => (),
+        EngineEvent::Discover(id) => {
+            let addr = e.store().get_peer(id)?.and_then(|p| p.addr);
+            println!("Discovered: {} ({:?})", id, addr);
+        },
+        EngineEvent::ReceivedData(id, sig) => {
+            match e.store().fetch_page(sig, [0u8; N])? {
+                Some(p) => println!("Data from {}: {:02x?}", id, p.body_raw()),
+                None => println!("Data from {}: {}", id, sig),
+            }
+        },
+        _ => println!("{:?}", evt),
+    }
+
+    Ok(())
+}
+
+fn print_peers(opts: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let store = SledStore::<SocketAddr>::new(&opts.store)?;
+
+    for (id, p) in store.peers() {
+        println!("{} addr: {:?} subscriber: {} subscribed: {:?} access: {:?}",
+            id, p.addr, p.subscriber, p.subscribed, p.access);
+    }
+
+    Ok(())
+}
+
+fn dump(opts: &Options, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let store = SledStore::<SocketAddr>::new(&opts.store)?;
+    let d = store.dump()?;
+
+    match json {
+        true => println!("{}", serde_json::to_string_pretty(&d)?),
+        false => print!("{}", d),
+    }
+
+    Ok(())
+}
+
+fn print_pages(opts: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let store = SledStore::<SocketAddr>::new(&opts.store)?;
+
+    for p in store.pages() {
+        let (sig, raw) = p?;
+        let (c, _n) = Container::from(raw);
+
+        println!("{} id: {} kind: {:?} index: {} body: {:02x?}",
+            sig, c.id(), c.header().kind(), c.header().index(), c.body_raw());
+    }
+
+    Ok(())
+}
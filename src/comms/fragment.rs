@@ -0,0 +1,340 @@
+//! Fragmentation and reassembly for small-MTU transports (LoRa, 802.15.4, etc.)
+//!
+//! Each fragment is prefixed with a header containing a message id (u16, LE),
+//! the fragment index and the fragment count.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::comms::Comms;
+use crate::log::{debug, warn};
+
+/// Length of the header prepended to each fragment
+pub const FRAG_HEADER_LEN: usize = 4;
+
+/// Default period in milliseconds after which incomplete messages are discarded
+pub const FRAG_TIMEOUT_MS: u64 = 5_000;
+
+/// Fragmentation error type
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FragError<E> {
+    /// Underlying comms error
+    Comms(E),
+    /// Message exceeds the maximum fragment count
+    TooLarge,
+}
+
+/// Reassembly slot for an incoming message
+#[derive(Debug)]
+struct Slot<Addr, const B: usize> {
+    from: Addr,
+    msg_id: u16,
+    count: u8,
+    received: [u8; 32],
+    total: Option<usize>,
+    started: u64,
+    data: [u8; B],
+}
+
+impl <Addr, const B: usize> Slot<Addr, B> {
+    fn complete(&self) -> bool {
+        self.total.is_some() && (0..self.count).all(|i| self.received[i as usize / 8] & (1 << (i % 8)) != 0)
+    }
+}
+
+/// Fragmenting [Comms] wrapper, splitting objects into fragments of up to `MTU` bytes
+///
+/// Incoming messages of up to `B` bytes are reassembled using at most `M` slots,
+/// with the oldest incomplete message evicted when all slots are in use.
+pub struct FragComms<C: Comms, const MTU: usize, const B: usize = 512, const M: usize = 4> {
+    inner: C,
+    msg_id: u16,
+    timeout_ms: u64,
+    slots: heapless::Vec<Slot<C::Address, B>, M>,
+
+    #[cfg(feature = "std")]
+    started: std::time::Instant,
+    time_ms: Option<u64>,
+}
+
+impl <C: Comms, const MTU: usize, const B: usize, const M: usize> FragComms<C, MTU, B, M>
+where
+    C::Address: Clone + PartialEq,
+{
+    /// Compile-time check that fragments have space for a payload
+    const MTU_VALID: () = assert!(MTU > FRAG_HEADER_LEN, "MTU must exceed fragment header length");
+
+    /// Create a new fragmenting wrapper over the provided comms
+    pub fn new(inner: C) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::MTU_VALID;
+
+        Self {
+            inner,
+            msg_id: 0,
+            timeout_ms: FRAG_TIMEOUT_MS,
+            slots: heapless::Vec::new(),
+            #[cfg(feature = "std")]
+            started: std::time::Instant::now(),
+            time_ms: None,
+        }
+    }
+
+    /// Set the period after which incomplete messages are discarded
+    pub fn set_timeout(&mut self, timeout_ms: u64) {
+        self.timeout_ms = timeout_ms;
+    }
+
+    /// Set the current time in milliseconds, replacing the system clock
+    pub fn set_time(&mut self, now_ms: u64) {
+        self.time_ms = Some(now_ms);
+    }
+
+    /// Fetch the underlying comms
+    pub fn inner(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    fn time(&self) -> u64 {
+        if let Some(t) = self.time_ms {
+            return t;
+        }
+
+        #[cfg(feature = "std")]
+        return self.started.elapsed().as_millis() as u64;
+
+        #[cfg(not(feature = "std"))]
+        return 0;
+    }
+
+    /// Split data into fragments, passing each to `f` for transmission
+    fn fragment<F>(&mut self, data: &[u8], mut f: F) -> Result<(), FragError<C::Error>>
+    where
+        F: FnMut(&mut C, &[u8]) -> Result<(), C::Error>,
+    {
+        let payload = MTU - FRAG_HEADER_LEN;
+        let count = ((data.len() + payload - 1) / payload).max(1);
+        if count > u8::MAX as usize {
+            return Err(FragError::TooLarge);
+        }
+
+        self.msg_id = self.msg_id.wrapping_add(1);
+
+        let mut frag = [0u8; MTU];
+        LittleEndian::write_u16(&mut frag[0..2], self.msg_id);
+        frag[3] = count as u8;
+
+        for i in 0..count {
+            let chunk = &data[i * payload..data.len().min((i + 1) * payload)];
+
+            frag[2] = i as u8;
+            frag[FRAG_HEADER_LEN..][..chunk.len()].copy_from_slice(chunk);
+
+            f(&mut self.inner, &frag[..FRAG_HEADER_LEN + chunk.len()]).map_err(FragError::Comms)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl <C: Comms, const MTU: usize, const B: usize, const M: usize> Comms for FragComms<C, MTU, B, M>
+where
+    C::Address: Clone + PartialEq,
+{
+    type Address = C::Address;
+
+    type Error = FragError<C::Error>;
+
+    fn recv(&mut self, buff: &mut [u8]) -> Result<Option<(usize, Self::Address)>, Self::Error> {
+        let payload = MTU - FRAG_HEADER_LEN;
+        let now = self.time();
+
+        // Expire incomplete messages
+        let timeout = self.timeout_ms;
+        self.slots.retain(|s| now.saturating_sub(s.started) < timeout);
+
+        let mut frag = [0u8; MTU];
+
+        loop {
+            let (n, from) = match self.inner.recv(&mut frag).map_err(FragError::Comms)? {
+                Some(v) => v,
+                None => return Ok(None),
+            };
+
+            if n < FRAG_HEADER_LEN {
+                debug!("Dropping short fragment ({} bytes)", n);
+                continue;
+            }
+
+            let msg_id = LittleEndian::read_u16(&frag[0..2]);
+            let (index, count) = (frag[2], frag[3]);
+            let body = &frag[FRAG_HEADER_LEN..n];
+
+            // Check fragment is valid, only the last fragment may be short
+            if count == 0 || index >= count || (index < count - 1 && body.len() != payload) {
+                debug!("Dropping invalid fragment {}/{} (msg: {})", index, count, msg_id);
+                continue;
+            }
+
+            // Unfragmented messages bypass reassembly
+            if count == 1 {
+                if body.len() > buff.len() {
+                    warn!("Dropping message exceeding receive buffer ({} bytes)", body.len());
+                    continue;
+                }
+
+                buff[..body.len()].copy_from_slice(body);
+                return Ok(Some((body.len(), from)));
+            }
+
+            let offset = index as usize * payload;
+            if offset + body.len() > B {
+                warn!("Dropping fragment exceeding reassembly buffer (msg: {})", msg_id);
+                continue;
+            }
+
+            // Find or allocate a reassembly slot, evicting the oldest where required
+            let i = match self.slots.iter().position(|s| s.from == from && s.msg_id == msg_id) {
+                Some(i) => i,
+                None => {
+                    if self.slots.is_full() {
+                        let oldest = self.slots.iter().enumerate()
+                            .min_by_key(|(_i, s)| s.started)
+                            .map(|(i, _s)| i);
+                        if let Some(i) = oldest {
+                            debug!("Evicting incomplete message (msg: {})", self.slots[i].msg_id);
+                            self.slots.swap_remove(i);
+                        }
+                    }
+
+                    let s = Slot{ from: from.clone(), msg_id, count, received: [0u8; 32], total: None, started: now, data: [0u8; B] };
+                    if self.slots.push(s).is_err() {
+                        continue;
+                    }
+                    self.slots.len() - 1
+                }
+            };
+
+            let s = &mut self.slots[i];
+            if s.count != count {
+                debug!("Dropping fragment with mismatched count (msg: {})", msg_id);
+                continue;
+            }
+
+            s.received[index as usize / 8] |= 1 << (index % 8);
+            s.data[offset..offset + body.len()].copy_from_slice(body);
+            if index == count - 1 {
+                s.total = Some(offset + body.len());
+            }
+
+            // Return completed messages
+            if s.complete() {
+                let s = self.slots.swap_remove(i);
+                let total = s.total.unwrap_or(0);
+
+                if total > buff.len() {
+                    warn!("Dropping message exceeding receive buffer ({} bytes)", total);
+                    continue;
+                }
+
+                buff[..total].copy_from_slice(&s.data[..total]);
+                return Ok(Some((total, s.from)));
+            }
+        }
+    }
+
+    fn send(&mut self, to: &Self::Address, data: &[u8]) -> Result<(), Self::Error> {
+        self.fragment(data, |c, f| c.send(to, f))
+    }
+
+    fn broadcast(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.fragment(data, |c, f| c.broadcast(f))
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod test {
+    use crate::comms::mock::MockComms;
+
+    use super::*;
+
+    type F = FragComms<MockComms, 64, 512, 2>;
+
+    #[test]
+    fn fragment_reassemble() {
+        let (mut c1, mut c2) = (F::new(MockComms::default()), F::new(MockComms::default()));
+        let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let mut buff = [0u8; 512];
+
+        c1.send(&2, &data).unwrap();
+
+        let frags = core::mem::take(&mut c1.inner().tx);
+        assert_eq!(frags.len(), 5);
+        assert!(frags.iter().all(|(to, f)| *to == 2 && f.len() <= 64));
+
+        // Deliver out of order, with a duplicate
+        for i in &[4, 0, 2, 2, 1, 3] {
+            c2.inner().rx.push((1, frags[*i].1.clone()));
+        }
+
+        assert_eq!(c2.recv(&mut buff).unwrap(), Some((300, 1)));
+        assert_eq!(&buff[..300], &data[..]);
+
+        // No further messages available
+        assert_eq!(c2.recv(&mut buff).unwrap(), None);
+
+        // Small messages are sent in a single fragment
+        c1.send(&2, &[0xaa, 0xbb]).unwrap();
+        let (_to, f) = c1.inner().tx.pop().unwrap();
+        c2.inner().rx.push((1, f));
+        assert_eq!(c2.recv(&mut buff).unwrap(), Some((2, 1)));
+        assert_eq!(&buff[..2], &[0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn fragment_limits() {
+        let (mut c1, mut c2) = (F::new(MockComms::default()), F::new(MockComms::default()));
+        let mut buff = [0u8; 512];
+
+        // Oversized messages are rejected
+        assert_eq!(c1.send(&2, &[0u8; 256 * 60]), Err(FragError::TooLarge));
+
+        // Incomplete messages expire
+        c2.set_timeout(10);
+        c1.send(&2, &[0x11; 100]).unwrap();
+        let frags = core::mem::take(&mut c1.inner().tx);
+
+        c2.inner().rx.push((1, frags[0].1.clone()));
+        assert_eq!(c2.recv(&mut buff).unwrap(), None);
+
+        c2.set_time(c2.time() + 20);
+
+        for (_to, f) in &frags[1..] {
+            c2.inner().rx.push((1, f.clone()));
+        }
+        assert_eq!(c2.recv(&mut buff).unwrap(), None);
+
+        // Slots are bounded, evicting the oldest incomplete message
+        let msgs: Vec<_> = (0..3).map(|i| {
+            c1.send(&2, &[i; 100]).unwrap();
+            core::mem::take(&mut c1.inner().tx)
+        }).collect();
+
+        for m in &msgs {
+            c2.inner().rx.push((1, m[0].1.clone()));
+        }
+        assert_eq!(c2.recv(&mut buff).unwrap(), None);
+        assert_eq!(c2.slots.len(), 2);
+
+        for (_to, f) in &msgs[0][1..] {
+            c2.inner().rx.push((1, f.clone()));
+        }
+        assert_eq!(c2.recv(&mut buff).unwrap(), None);
+
+        for (_to, f) in &msgs[2][1..] {
+            c2.inner().rx.push((1, f.clone()));
+        }
+        assert_eq!(c2.recv(&mut buff).unwrap(), Some((100, 1)));
+        assert_eq!(&buff[..100], &[2u8; 100][..]);
+    }
+}
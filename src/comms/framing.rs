@@ -0,0 +1,284 @@
+//! Byte stream framing for serial transports, using SLIP or COBS with a CRC16 trailer
+
+use crate::log::debug;
+
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+/// Length of the CRC trailer appended to each frame
+pub const CRC_LEN: usize = 2;
+
+/// Frame encoding for byte stream transports
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Framing {
+    /// Serial Line Internet Protocol (RFC 1055) framing
+    Slip,
+    /// Consistent Overhead Byte Stuffing, with zero delimiters
+    Cobs,
+}
+
+/// Compute a CRC-16/CCITT-FALSE over the provided data
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+
+    for b in data {
+        crc ^= (*b as u16) << 8;
+        for _i in 0..8 {
+            crc = match crc & 0x8000 != 0 {
+                true => (crc << 1) ^ 0x1021,
+                false => crc << 1,
+            };
+        }
+    }
+
+    crc
+}
+
+/// Encode a frame with CRC trailer, passing encoded bytes to `write`
+pub fn encode<E, F: FnMut(u8) -> Result<(), E>>(framing: Framing, data: &[u8], mut write: F) -> Result<(), E> {
+    let crc = crc16(data).to_le_bytes();
+    let len = data.len() + CRC_LEN;
+    let byte = |i: usize| if i < data.len() { data[i] } else { crc[i - data.len()] };
+
+    match framing {
+        Framing::Slip => {
+            // Leading END flushes any line noise at the receiver
+            write(SLIP_END)?;
+
+            for i in 0..len {
+                match byte(i) {
+                    SLIP_END => { write(SLIP_ESC)?; write(SLIP_ESC_END)?; },
+                    SLIP_ESC => { write(SLIP_ESC)?; write(SLIP_ESC_ESC)?; },
+                    b => write(b)?,
+                }
+            }
+
+            write(SLIP_END)
+        },
+        Framing::Cobs => {
+            let mut start = 0;
+
+            loop {
+                // Find the next zero or maximum block length
+                let mut end = start;
+                while end < len && byte(end) != 0 && end - start < 254 {
+                    end += 1;
+                }
+
+                let n = end - start;
+                write(n as u8 + 1)?;
+                for i in start..end {
+                    write(byte(i))?;
+                }
+
+                if end == len {
+                    break;
+                }
+
+                // Maximum length blocks have no implied zero
+                if n == 254 {
+                    start = end;
+                    continue;
+                }
+
+                start = end + 1;
+
+                // Trailing zeros require an empty final block
+                if start == len {
+                    write(1)?;
+                    break;
+                }
+            }
+
+            write(0)
+        },
+    }
+}
+
+/// Decode a COBS encoded buffer (excluding the delimiter) in place, returning the decoded length
+fn cobs_decode(buff: &mut [u8]) -> Option<usize> {
+    let (mut r, mut w) = (0, 0);
+
+    while r < buff.len() {
+        let code = buff[r] as usize;
+        if code == 0 || r + code > buff.len() {
+            return None;
+        }
+        r += 1;
+
+        for _i in 1..code {
+            buff[w] = buff[r];
+            w += 1;
+            r += 1;
+        }
+
+        if code < 0xFF && r < buff.len() {
+            buff[w] = 0;
+            w += 1;
+        }
+    }
+
+    Some(w)
+}
+
+/// Frame decoder, accumulating received bytes into frames of up to `B` bytes
+#[derive(Debug)]
+pub struct Deframer<const B: usize> {
+    framing: Framing,
+    buff: heapless::Vec<u8, B>,
+    escape: bool,
+    invalid: bool,
+}
+
+impl <const B: usize> Deframer<B> {
+    /// Create a new frame decoder
+    pub fn new(framing: Framing) -> Self {
+        Self {
+            framing,
+            buff: heapless::Vec::new(),
+            escape: false,
+            invalid: false,
+        }
+    }
+
+    /// Push a received byte, copying completed and CRC checked frames to `out`
+    /// and returning the frame length
+    ///
+    /// Invalid, oversized and corrupted frames are dropped.
+    pub fn push(&mut self, b: u8, out: &mut [u8]) -> Option<usize> {
+        match (self.framing, b) {
+            (Framing::Slip, SLIP_END) | (Framing::Cobs, 0) => return self.complete(out),
+            (Framing::Slip, SLIP_ESC) => {
+                self.escape = true;
+                return None;
+            },
+            _ => (),
+        }
+
+        // Unescape SLIP bytes
+        let b = match (self.framing, self.escape, b) {
+            (Framing::Slip, true, SLIP_ESC_END) => SLIP_END,
+            (Framing::Slip, true, SLIP_ESC_ESC) => SLIP_ESC,
+            (Framing::Slip, true, _) => {
+                self.invalid = true;
+                b
+            },
+            _ => b,
+        };
+        self.escape = false;
+
+        if self.buff.push(b).is_err() {
+            self.invalid = true;
+        }
+
+        None
+    }
+
+    fn complete(&mut self, out: &mut [u8]) -> Option<usize> {
+        let invalid = self.invalid;
+        self.invalid = false;
+        self.escape = false;
+
+        // Skip empty frames (ie. SLIP leading END)
+        if self.buff.is_empty() {
+            return None;
+        }
+
+        let mut n = self.buff.len();
+        if self.framing == Framing::Cobs {
+            n = cobs_decode(&mut self.buff).unwrap_or(0);
+        }
+
+        let r = match n {
+            _ if invalid => {
+                debug!("Dropping invalid or oversized frame");
+                None
+            },
+            n if n < CRC_LEN || n - CRC_LEN > out.len() => {
+                debug!("Dropping frame with invalid length: {}", n);
+                None
+            },
+            n => {
+                let (data, crc) = self.buff[..n].split_at(n - CRC_LEN);
+                if crc16(data).to_le_bytes() == crc {
+                    out[..data.len()].copy_from_slice(data);
+                    Some(data.len())
+                } else {
+                    debug!("Dropping frame with invalid CRC");
+                    None
+                }
+            },
+        };
+
+        self.buff.clear();
+        r
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn roundtrip(framing: Framing, data: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        encode(framing, data, |b| { encoded.push(b); Ok::<_, ()>(()) }).unwrap();
+
+        let mut d = Deframer::<1024>::new(framing);
+        let mut out = [0u8; 1024];
+        let mut frames = vec![];
+        for b in &encoded {
+            if let Some(n) = d.push(*b, &mut out) {
+                frames.push(out[..n].to_vec());
+            }
+        }
+
+        assert_eq!(frames.len(), 1, "Expected single frame for {:02x?}", encoded);
+        frames.remove(0)
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn framing_roundtrip() {
+        let tests = [
+            vec![0x11],
+            vec![0x00],
+            vec![0x11, 0x00, 0x22, 0x00],
+            vec![SLIP_END, SLIP_ESC, SLIP_ESC_END, 0x00, SLIP_END],
+            (1..=255u8).cycle().take(600).collect(),
+            vec![0x00; 300],
+        ];
+
+        for framing in &[Framing::Slip, Framing::Cobs] {
+            for data in &tests {
+                assert_eq!(&roundtrip(*framing, data), data, "Roundtrip failed for {:?}", framing);
+            }
+        }
+    }
+
+    #[test]
+    fn framing_corrupt() {
+        for framing in &[Framing::Slip, Framing::Cobs] {
+            let mut encoded = Vec::new();
+            encode(*framing, &[0x11, 0x22, 0x33], |b| { encoded.push(b); Ok::<_, ()>(()) }).unwrap();
+
+            // Corrupt payload byte
+            encoded[2] ^= 0x01;
+
+            let mut d = Deframer::<64>::new(*framing);
+            let mut out = [0u8; 64];
+            assert!(encoded.iter().all(|b| d.push(*b, &mut out).is_none()));
+
+            // Oversized frames are dropped
+            let mut encoded = Vec::new();
+            encode(*framing, &[0x11; 100], |b| { encoded.push(b); Ok::<_, ()>(()) }).unwrap();
+            assert!(encoded.iter().all(|b| d.push(*b, &mut out).is_none()));
+        }
+    }
+}
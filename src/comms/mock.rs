@@ -6,11 +6,13 @@ use alloc::vec::Vec;
 /// Mock comms interface for test use
 pub struct MockComms {
     pub(crate) tx: Vec<(u8, Vec<u8>)>,
+    pub(crate) rx: Vec<(u8, Vec<u8>)>,
+    pub(crate) broadcast: Vec<Vec<u8>>,
 }
 
 impl Default for MockComms {
     fn default() -> Self {
-        Self { tx: Vec::new() }
+        Self { tx: Vec::new(), rx: Vec::new(), broadcast: Vec::new() }
     }
 }
 
@@ -19,8 +21,15 @@ impl Comms for MockComms {
 
     type Error = core::convert::Infallible;
 
-    fn recv(&mut self, _buff: &mut [u8]) -> Result<Option<(usize, Self::Address)>, Self::Error> {
-        todo!()
+    fn recv(&mut self, buff: &mut [u8]) -> Result<Option<(usize, Self::Address)>, Self::Error> {
+        if self.rx.is_empty() {
+            return Ok(None);
+        }
+
+        let (from, data) = self.rx.remove(0);
+        buff[..data.len()].copy_from_slice(&data);
+
+        Ok(Some((data.len(), from)))
     }
 
     fn send(&mut self, to: &Self::Address, data: &[u8]) -> Result<(), Self::Error> {
@@ -28,7 +37,8 @@ impl Comms for MockComms {
         Ok(())
     }
 
-    fn broadcast(&mut self, _data: &[u8]) -> Result<(), Self::Error> {
-        todo!()
+    fn broadcast(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.broadcast.push(data.to_vec());
+        Ok(())
     }
 }
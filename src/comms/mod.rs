@@ -11,6 +11,23 @@ pub mod mock;
 
 pub mod udp;
 
+pub mod framing;
+
+pub mod serial;
+
+pub mod fragment;
+
+pub mod multi;
+
+#[cfg(feature = "std")]
+pub mod tcp;
+
+#[cfg(all(feature = "std", unix))]
+pub mod unix;
+
+#[cfg(feature = "std")]
+pub mod capture;
+
 /// Abstract communication interface trait
 pub trait Comms {
     /// Address for directing packets
@@ -0,0 +1,184 @@
+//! Composite [Comms] implementation, bridging multiple transports
+//!
+//! [MultiComms] combines two [Comms] implementations, routing `send` by address variant,
+//! receiving from both and broadcasting on each. Further transports may be added by
+//! nesting, for example `MultiComms<UdpSocket, MultiComms<SerialComms<T>, TcpComms>>`,
+//! where addresses are nested in the same manner (ie. a TCP address in the example is
+//! `MultiAddress::Second(MultiAddress::Second(addr))`).
+//!
+//! Receive polling alternates at each level, so where every transport has pending data
+//! the outer transport is polled first half of the time and each nested transport a
+//! quarter. Place higher priority transports at the outer level.
+
+use crate::comms::Comms;
+use crate::log::warn;
+
+/// Address for a [MultiComms] transport, nested for more than two transports
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MultiAddress<A, B> {
+    /// Address on the first transport
+    First(A),
+    /// Address on the second transport
+    Second(B),
+}
+
+/// Error for a [MultiComms] transport
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MultiError<A, B> {
+    /// Error on the first transport
+    First(A),
+    /// Error on the second transport
+    Second(B),
+}
+
+/// Composite [Comms] over two transports
+pub struct MultiComms<A, B> {
+    first: A,
+    second: B,
+    next: bool,
+}
+
+impl <A: Comms, B: Comms> MultiComms<A, B> {
+    /// Create a new composite comms instance
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, second, next: false }
+    }
+
+    /// Fetch the first transport
+    pub fn first(&mut self) -> &mut A {
+        &mut self.first
+    }
+
+    /// Fetch the second transport
+    pub fn second(&mut self) -> &mut B {
+        &mut self.second
+    }
+
+    fn recv_first(&mut self, buff: &mut [u8]) -> Result<Option<(usize, MultiAddress<A::Address, B::Address>)>, MultiError<A::Error, B::Error>> {
+        let r = self.first.recv(buff).map_err(MultiError::First)?;
+        Ok(r.map(|(n, a)| (n, MultiAddress::First(a))))
+    }
+
+    fn recv_second(&mut self, buff: &mut [u8]) -> Result<Option<(usize, MultiAddress<A::Address, B::Address>)>, MultiError<A::Error, B::Error>> {
+        let r = self.second.recv(buff).map_err(MultiError::Second)?;
+        Ok(r.map(|(n, a)| (n, MultiAddress::Second(a))))
+    }
+}
+
+impl <A: Comms, B: Comms> Comms for MultiComms<A, B> {
+    type Address = MultiAddress<A::Address, B::Address>;
+
+    type Error = MultiError<A::Error, B::Error>;
+
+    fn recv(&mut self, buff: &mut [u8]) -> Result<Option<(usize, Self::Address)>, Self::Error> {
+        // Alternate the first transport polled so neither starves the other
+        self.next = !self.next;
+
+        match self.next {
+            true => match self.recv_first(buff)? {
+                Some(v) => Ok(Some(v)),
+                None => self.recv_second(buff),
+            },
+            false => match self.recv_second(buff)? {
+                Some(v) => Ok(Some(v)),
+                None => self.recv_first(buff),
+            },
+        }
+    }
+
+    fn send(&mut self, to: &Self::Address, data: &[u8]) -> Result<(), Self::Error> {
+        match to {
+            MultiAddress::First(a) => self.first.send(a, data).map_err(MultiError::First),
+            MultiAddress::Second(b) => self.second.send(b, data).map_err(MultiError::Second),
+        }
+    }
+
+    fn broadcast(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        // Broadcast on every transport, reporting the first error
+        let r1 = self.first.broadcast(data).map_err(MultiError::First);
+        let r2 = self.second.broadcast(data).map_err(MultiError::Second);
+
+        if let Err(e) = &r1 {
+            warn!("Broadcast failed on first transport: {:?}", e);
+        }
+        if let Err(e) = &r2 {
+            warn!("Broadcast failed on second transport: {:?}", e);
+        }
+
+        r1.and(r2)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod test {
+    use crate::comms::mock::MockComms;
+
+    use super::*;
+
+    #[test]
+    fn multi_comms() {
+        let mut c = MultiComms::new(MockComms::default(), MockComms::default());
+        let mut buff = [0u8; 16];
+
+        // Receive from both transports
+        c.first().rx.push((1, vec![0x11]));
+        c.second().rx.push((2, vec![0x22, 0x33]));
+        c.second().rx.push((3, vec![0x44]));
+
+        let mut received = vec![];
+        while let Some((n, a)) = c.recv(&mut buff).unwrap() {
+            received.push((a, buff[..n].to_vec()));
+        }
+
+        assert_eq!(received.len(), 3);
+        assert!(received.contains(&(MultiAddress::First(1), vec![0x11])));
+        assert!(received.contains(&(MultiAddress::Second(2), vec![0x22, 0x33])));
+        assert!(received.contains(&(MultiAddress::Second(3), vec![0x44])));
+
+        // Route sends by address
+        c.send(&MultiAddress::Second(4), &[0x55]).unwrap();
+        assert_eq!(c.first().tx.len(), 0);
+        assert_eq!(c.second().tx.pop(), Some((4, vec![0x55])));
+
+        // Broadcast on all transports
+        c.broadcast(&[0x66]).unwrap();
+        assert_eq!(c.first().broadcast, vec![vec![0x66]]);
+        assert_eq!(c.second().broadcast, vec![vec![0x66]]);
+    }
+
+    #[test]
+    fn multi_comms_nested() {
+        let inner = MultiComms::new(MockComms::default(), MockComms::default());
+        let mut c = MultiComms::new(MockComms::default(), inner);
+        let mut buff = [0u8; 16];
+
+        // Receive from all three transports
+        c.first().rx.push((1, vec![0x11]));
+        c.second().first().rx.push((2, vec![0x22]));
+        c.second().second().rx.push((3, vec![0x33]));
+
+        let mut received = vec![];
+        while let Some((n, a)) = c.recv(&mut buff).unwrap() {
+            received.push((a, buff[..n].to_vec()));
+        }
+
+        assert_eq!(received.len(), 3);
+        assert!(received.contains(&(MultiAddress::First(1), vec![0x11])));
+        assert!(received.contains(&(MultiAddress::Second(MultiAddress::First(2)), vec![0x22])));
+        assert!(received.contains(&(MultiAddress::Second(MultiAddress::Second(3)), vec![0x33])));
+
+        // Route sends through nested addresses
+        c.send(&MultiAddress::Second(MultiAddress::Second(4)), &[0x44]).unwrap();
+        assert_eq!(c.first().tx.len(), 0);
+        assert_eq!(c.second().first().tx.len(), 0);
+        assert_eq!(c.second().second().tx.pop(), Some((4, vec![0x44])));
+
+        // Broadcast on all transports
+        c.broadcast(&[0x66]).unwrap();
+        assert_eq!(c.first().broadcast, vec![vec![0x66]]);
+        assert_eq!(c.second().first().broadcast, vec![vec![0x66]]);
+        assert_eq!(c.second().second().broadcast, vec![vec![0x66]]);
+    }
+}
@@ -0,0 +1,172 @@
+//! Serial [Comms] implementations, framing DSF objects over byte streams (RS-485, USB-CDC, etc.)
+//!
+//! Serial links are point-to-point, so the address type is `()` and
+//! broadcasts are sent as normal frames.
+
+use crate::comms::{
+    Comms,
+    framing::{Framing, Deframer, encode},
+};
+
+/// Default maximum encoded frame length for serial transports
+pub const SERIAL_FRAME_LEN: usize = 1024;
+
+/// Serial [Comms] over any [std::io::Read] + [std::io::Write] byte stream
+///
+/// Reads should be non-blocking or configured with a timeout, as `WouldBlock`
+/// and `TimedOut` errors are treated as no data being available.
+#[cfg(feature = "std")]
+pub struct SerialComms<T, const B: usize = SERIAL_FRAME_LEN> {
+    port: T,
+    framing: Framing,
+    rx: Deframer<B>,
+}
+
+#[cfg(feature = "std")]
+impl <T: std::io::Read + std::io::Write, const B: usize> SerialComms<T, B> {
+    /// Create a new serial comms instance with the provided framing
+    pub fn new(port: T, framing: Framing) -> Self {
+        Self { port, framing, rx: Deframer::new(framing) }
+    }
+
+    /// Fetch the underlying byte stream
+    pub fn inner(&mut self) -> &mut T {
+        &mut self.port
+    }
+
+    /// Consume the comms instance, returning the underlying byte stream
+    pub fn into_inner(self) -> T {
+        self.port
+    }
+}
+
+#[cfg(feature = "std")]
+impl <T: std::io::Read + std::io::Write, const B: usize> Comms for SerialComms<T, B> {
+    type Address = ();
+
+    type Error = std::io::Error;
+
+    fn recv(&mut self, buff: &mut [u8]) -> Result<Option<(usize, Self::Address)>, Self::Error> {
+        use std::io::ErrorKind;
+
+        let mut b = [0u8; 1];
+
+        loop {
+            match self.port.read(&mut b) {
+                Ok(0) => return Ok(None),
+                Ok(_) => if let Some(n) = self.rx.push(b[0], buff) {
+                    return Ok(Some((n, ())));
+                },
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn send(&mut self, _to: &Self::Address, data: &[u8]) -> Result<(), Self::Error> {
+        let mut frame = Vec::with_capacity(data.len() * 2);
+        let _ = encode(self.framing, data, |b| {
+            frame.push(b);
+            Ok::<_, core::convert::Infallible>(())
+        });
+
+        self.port.write_all(&frame)?;
+        self.port.flush()
+    }
+
+    fn broadcast(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.send(&(), data)
+    }
+}
+
+/// Serial [Comms] over an [embedded_hal::serial] peripheral
+#[cfg(feature = "hal")]
+pub struct HalSerialComms<S, const B: usize = SERIAL_FRAME_LEN> {
+    port: S,
+    framing: Framing,
+    rx: Deframer<B>,
+}
+
+#[cfg(feature = "hal")]
+impl <S, const B: usize> HalSerialComms<S, B> {
+    /// Create a new serial comms instance with the provided framing
+    pub fn new(port: S, framing: Framing) -> Self {
+        Self { port, framing, rx: Deframer::new(framing) }
+    }
+
+    /// Consume the comms instance, returning the underlying peripheral
+    pub fn into_inner(self) -> S {
+        self.port
+    }
+}
+
+#[cfg(feature = "hal")]
+impl <S, E, const B: usize> Comms for HalSerialComms<S, B>
+where
+    S: embedded_hal::serial::Read<u8, Error = E> + embedded_hal::serial::Write<u8, Error = E>,
+    E: core::fmt::Debug,
+{
+    type Address = ();
+
+    type Error = E;
+
+    fn recv(&mut self, buff: &mut [u8]) -> Result<Option<(usize, Self::Address)>, Self::Error> {
+        loop {
+            match self.port.read() {
+                Ok(b) => if let Some(n) = self.rx.push(b, buff) {
+                    return Ok(Some((n, ())));
+                },
+                Err(nb::Error::WouldBlock) => return Ok(None),
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+        }
+    }
+
+    fn send(&mut self, _to: &Self::Address, data: &[u8]) -> Result<(), Self::Error> {
+        let port = &mut self.port;
+
+        encode(self.framing, data, |b| nb::block!(port.write(b)))?;
+
+        nb::block!(port.flush())
+    }
+
+    fn broadcast(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.send(&(), data)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::os::unix::net::UnixStream;
+
+    use super::*;
+
+    #[test]
+    fn serial_pipe() {
+        for framing in &[Framing::Slip, Framing::Cobs] {
+            let (a, b) = UnixStream::pair().unwrap();
+            a.set_nonblocking(true).unwrap();
+            b.set_nonblocking(true).unwrap();
+
+            let mut c1 = SerialComms::<_>::new(a, *framing);
+            let mut c2 = SerialComms::<_>::new(b, *framing);
+            let mut buff = [0u8; 512];
+
+            // No data available
+            assert_eq!(c2.recv(&mut buff).unwrap(), None);
+
+            // Frames are delivered in order
+            c1.send(&(), &[0x11, 0x00, 0x22]).unwrap();
+            c1.broadcast(&[0xC0, 0xDB]).unwrap();
+
+            assert_eq!(c2.recv(&mut buff).unwrap(), Some((3, ())));
+            assert_eq!(&buff[..3], &[0x11, 0x00, 0x22]);
+
+            assert_eq!(c2.recv(&mut buff).unwrap(), Some((2, ())));
+            assert_eq!(&buff[..2], &[0xC0, 0xDB]);
+
+            assert_eq!(c2.recv(&mut buff).unwrap(), None);
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! TCP [Comms] implementation, for networks where UDP is unavailable
+//!
+//! Objects are sent as frames prefixed with a u16 (LE) length, over a pool of
+//! connections keyed by peer address. Outbound connections are established
+//! on the first `send` to an address, and inbound connections are accepted
+//! (and keyed by the remote address) on `recv`, with responses sent over the
+//! same connection.
+//!
+//! TCP has no broadcast mechanism, so `broadcast` sends to all connected peers.
+//!
+//! Connecting and writing are bounded by a timeout (see [TcpComms::set_timeout]),
+//! connections failing to connect or accept a frame in this period are dropped.
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::comms::Comms;
+use crate::log::{debug, warn};
+
+/// Length of the frame length prefix
+pub const TCP_HEADER_LEN: usize = 2;
+
+/// Maximum buffered receive data per connection, one maximum length frame
+pub const TCP_RX_MAX: usize = TCP_HEADER_LEN + u16::MAX as usize;
+
+/// Default connect and write timeout
+pub const TCP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Pooled TCP connection
+struct Conn {
+    stream: TcpStream,
+    rx: Vec<u8>,
+}
+
+impl Conn {
+    fn new(stream: TcpStream, timeout: Duration) -> Result<Self, std::io::Error> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        Ok(Self { stream, rx: Vec::new() })
+    }
+
+    /// Read available data up to [TCP_RX_MAX] buffered bytes, returning false if the connection has closed
+    fn read(&mut self) -> Result<bool, std::io::Error> {
+        let mut buff = [0u8; 512];
+
+        while self.rx.len() < TCP_RX_MAX {
+            let n = buff.len().min(TCP_RX_MAX - self.rx.len());
+
+            match self.stream.read(&mut buff[..n]) {
+                Ok(0) => return Ok(false),
+                Ok(n) => self.rx.extend_from_slice(&buff[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        // Remaining data is read once buffered frames are consumed
+        Ok(true)
+    }
+
+    /// Take a completed frame from the receive buffer
+    fn frame(&mut self, buff: &mut [u8]) -> Option<usize> {
+        loop {
+            if self.rx.len() < TCP_HEADER_LEN {
+                return None;
+            }
+
+            let n = u16::from_le_bytes([self.rx[0], self.rx[1]]) as usize;
+            if self.rx.len() < TCP_HEADER_LEN + n {
+                return None;
+            }
+
+            let frame: Vec<u8> = self.rx.drain(..TCP_HEADER_LEN + n).skip(TCP_HEADER_LEN).collect();
+            if n > buff.len() {
+                warn!("Dropping frame exceeding receive buffer ({} bytes)", n);
+                continue;
+            }
+
+            buff[..n].copy_from_slice(&frame);
+            return Some(n);
+        }
+    }
+
+    /// Write a length-prefixed frame
+    fn write(&mut self, data: &[u8]) -> Result<(), std::io::Error> {
+        if data.len() > u16::MAX as usize {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "frame exceeds maximum length"));
+        }
+
+        // Writes block (up to the write timeout) to avoid partial frames
+        self.stream.set_nonblocking(false)?;
+
+        let r = self.stream.write_all(&(data.len() as u16).to_le_bytes())
+            .and_then(|_| self.stream.write_all(data));
+
+        self.stream.set_nonblocking(true)?;
+
+        r
+    }
+}
+
+/// TCP [Comms] with a connection pool keyed by peer address
+pub struct TcpComms {
+    listener: TcpListener,
+    conns: HashMap<SocketAddr, Conn>,
+    timeout: Duration,
+}
+
+impl TcpComms {
+    /// Bind a new TCP comms instance to the provided address
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self, std::io::Error> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self { listener, conns: HashMap::new(), timeout: TCP_TIMEOUT })
+    }
+
+    /// Set the connect and write timeout for subsequent connections
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Fetch the local listening address
+    pub fn local_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        self.listener.local_addr()
+    }
+
+    /// Fetch addresses of connected peers
+    pub fn peers(&self) -> impl Iterator<Item=&SocketAddr> {
+        self.conns.keys()
+    }
+
+    /// Close the connection to a peer, returning false if no connection exists
+    pub fn disconnect(&mut self, addr: &SocketAddr) -> bool {
+        self.conns.remove(addr).is_some()
+    }
+
+    /// Accept pending inbound connections
+    fn accept(&mut self) -> Result<(), std::io::Error> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    debug!("Accepted connection from: {}", addr);
+                    self.conns.insert(addr, Conn::new(stream, self.timeout)?);
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Comms for TcpComms {
+    type Address = SocketAddr;
+
+    type Error = std::io::Error;
+
+    fn recv(&mut self, buff: &mut [u8]) -> Result<Option<(usize, Self::Address)>, Self::Error> {
+        self.accept()?;
+
+        let mut closed = vec![];
+        let mut received = None;
+
+        for (addr, c) in self.conns.iter_mut() {
+            // Read available data, noting closed connections
+            match c.read() {
+                Ok(true) => (),
+                Ok(false) => closed.push(*addr),
+                Err(e) => {
+                    warn!("Connection error for {}: {:?}", addr, e);
+                    closed.push(*addr);
+                },
+            }
+
+            if let Some(n) = c.frame(buff) {
+                received = Some((n, *addr));
+                break;
+            }
+        }
+
+        for addr in closed {
+            debug!("Connection closed: {}", addr);
+            self.conns.remove(&addr);
+        }
+
+        Ok(received)
+    }
+
+    fn send(&mut self, to: &Self::Address, data: &[u8]) -> Result<(), Self::Error> {
+        // Lazily connect to peers
+        if !self.conns.contains_key(to) {
+            debug!("Connecting to: {}", to);
+            let stream = TcpStream::connect_timeout(to, self.timeout)?;
+            self.conns.insert(*to, Conn::new(stream, self.timeout)?);
+        }
+
+        let c = self.conns.get_mut(to).unwrap();
+
+        if let Err(e) = c.write(data) {
+            warn!("Send error for {}: {:?}", to, e);
+            self.conns.remove(to);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    fn broadcast(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        let mut failed = vec![];
+
+        for (addr, c) in self.conns.iter_mut() {
+            if let Err(e) = c.write(data) {
+                warn!("Broadcast error for {}: {:?}", addr, e);
+                failed.push(*addr);
+            }
+        }
+
+        for addr in failed {
+            self.conns.remove(&addr);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn recv_timeout(c: &mut TcpComms, buff: &mut [u8]) -> Option<(usize, SocketAddr)> {
+        let start = Instant::now();
+
+        while start.elapsed() < Duration::from_secs(1) {
+            if let Some(v) = c.recv(buff).unwrap() {
+                return Some(v);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        None
+    }
+
+    #[test]
+    fn tcp_comms() {
+        let mut c1 = TcpComms::bind("127.0.0.1:0").unwrap();
+        let mut c2 = TcpComms::bind("127.0.0.1:0").unwrap();
+        let a2 = c2.local_addr().unwrap();
+        let mut buff = [0u8; 512];
+
+        // Connect on send, accepting on recv
+        c1.send(&a2, &[0x11, 0x22, 0x33]).unwrap();
+        c1.send(&a2, &[0x44]).unwrap();
+
+        let (n, a1) = recv_timeout(&mut c2, &mut buff).expect("No frame received");
+        assert_eq!(&buff[..n], &[0x11, 0x22, 0x33]);
+
+        let (n, _a) = recv_timeout(&mut c2, &mut buff).expect("No frame received");
+        assert_eq!(&buff[..n], &[0x44]);
+
+        // Respond over the accepted connection
+        c2.send(&a1, &[0x55, 0x66]).unwrap();
+
+        let (n, a) = recv_timeout(&mut c1, &mut buff).expect("No frame received");
+        assert_eq!((&buff[..n], a), (&[0x55, 0x66][..], a2));
+
+        // Broadcast to connected peers
+        c2.broadcast(&[0x77]).unwrap();
+
+        let (n, _a) = recv_timeout(&mut c1, &mut buff).expect("No frame received");
+        assert_eq!(&buff[..n], &[0x77]);
+
+        // Closed connections are removed
+        c1.disconnect(&a2);
+        assert_eq!(recv_timeout(&mut c2, &mut buff), None);
+        assert_eq!(c2.peers().count(), 0);
+    }
+
+    #[test]
+    fn tcp_comms_rx_limit() {
+        let mut c1 = TcpComms::bind("127.0.0.1:0").unwrap();
+        let mut c2 = TcpComms::bind("127.0.0.1:0").unwrap();
+        let a2 = c2.local_addr().unwrap();
+        let mut buff = vec![0u8; u16::MAX as usize];
+
+        let frames: Vec<Vec<u8>> = (0..3u8).map(|i| vec![i; 40_000]).collect();
+        for f in &frames {
+            c1.send(&a2, f).unwrap();
+        }
+
+        // Buffered data is bounded, with remaining frames read as buffered frames are consumed
+        for f in &frames {
+            let (n, _a) = recv_timeout(&mut c2, &mut buff).expect("No frame received");
+            assert_eq!(&buff[..n], &f[..]);
+            assert!(c2.conns.values().all(|c| c.rx.len() <= TCP_RX_MAX));
+        }
+    }
+}
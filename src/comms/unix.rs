@@ -0,0 +1,123 @@
+//! Unix datagram socket [Comms] implementation, for communication between local processes
+//!
+//! Peers are addressed by socket path, so sockets must be bound to receive responses.
+//!
+//! Unix sockets have no broadcast mechanism, so `broadcast` sends to all other
+//! sockets in the directory containing the bound socket. As this would otherwise
+//! reach unrelated sockets (ie. in `/tmp` or `/run`), broadcasts are only sent
+//! from directories dedicated to DSF sockets, marked with [UNIX_BROADCAST_MARKER]
+//! by [broadcast_dir]. Broadcasting from other directories returns an error.
+
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+
+use crate::comms::Comms;
+use crate::log::{debug, warn};
+
+/// Marker file identifying a directory dedicated to DSF sockets
+pub const UNIX_BROADCAST_MARKER: &str = ".dsf-broadcast";
+
+/// Create a directory dedicated to DSF sockets, enabling broadcasts between sockets bound within it
+pub fn broadcast_dir<P: AsRef<Path>>(dir: P) -> Result<(), std::io::Error> {
+    std::fs::create_dir_all(&dir)?;
+    std::fs::OpenOptions::new().create(true).write(true).open(dir.as_ref().join(UNIX_BROADCAST_MARKER))?;
+    Ok(())
+}
+
+/// [Comms] implementation for [std::os::unix::net::UnixDatagram]
+impl Comms for UnixDatagram {
+    type Address = PathBuf;
+
+    type Error = std::io::Error;
+
+    fn recv(&mut self, buff: &mut [u8]) -> Result<Option<(usize, Self::Address)>, Self::Error> {
+        loop {
+            let (n, addr) = match self.recv_from(buff) {
+                Ok(v) => v,
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(e),
+            };
+
+            // Drop datagrams from unbound sockets, as these cannot be responded to
+            match addr.as_pathname() {
+                Some(p) => return Ok(Some((n, p.to_path_buf()))),
+                None => warn!("Dropping {} bytes from unbound socket", n),
+            }
+        }
+    }
+
+    fn send(&mut self, to: &Self::Address, data: &[u8]) -> Result<(), Self::Error> {
+        self.send_to(data, to)?;
+        Ok(())
+    }
+
+    fn broadcast(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        let local = self.local_addr()?;
+        let (local, dir) = match local.as_pathname().and_then(|p| p.parent().map(|d| (p, d))) {
+            Some(v) => v,
+            None => return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "broadcast requires a bound socket")),
+        };
+
+        if !dir.join(UNIX_BROADCAST_MARKER).is_file() {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "broadcast requires a dedicated socket directory"));
+        }
+
+        for e in std::fs::read_dir(dir)? {
+            let e = e?;
+            if !e.file_type()?.is_socket() || e.path() == local {
+                continue;
+            }
+
+            // Ignore stale sockets and non-datagram sockets
+            match self.send_to(data, e.path()) {
+                Ok(_) => debug!("Broadcast {} bytes to: {:?}", data.len(), e.path()),
+                Err(err) => debug!("Broadcast to {:?} failed: {:?}", e.path(), err),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unix_comms() {
+        let d = tempfile::tempdir().unwrap();
+        let (p1, p2) = (d.path().join("a.sock"), d.path().join("b.sock"));
+
+        let mut c1 = UnixDatagram::bind(&p1).unwrap();
+        let mut c2 = UnixDatagram::bind(&p2).unwrap();
+        c1.set_nonblocking(true).unwrap();
+        c2.set_nonblocking(true).unwrap();
+
+        let mut buff = [0u8; 512];
+        assert_eq!(c2.recv(&mut buff).unwrap(), None);
+
+        // Send and respond by path
+        c1.send(&p2, &[0x11, 0x22]).unwrap();
+        assert_eq!(c2.recv(&mut buff).unwrap(), Some((2, p1.clone())));
+
+        c2.send(&p1, &[0x33]).unwrap();
+        assert_eq!(c1.recv(&mut buff).unwrap(), Some((1, p2.clone())));
+
+        // Datagrams from unbound sockets are dropped
+        let u = UnixDatagram::unbound().unwrap();
+        u.send_to(&[0x44], &p2).unwrap();
+        assert_eq!(c2.recv(&mut buff).unwrap(), None);
+
+        // Broadcasts require a dedicated directory
+        assert!(c1.broadcast(&[0x55]).is_err());
+        assert_eq!(c2.recv(&mut buff).unwrap(), None);
+
+        // Broadcasts are sent to other sockets in the same directory
+        broadcast_dir(d.path()).unwrap();
+
+        c1.broadcast(&[0x55]).unwrap();
+        assert_eq!(c2.recv(&mut buff).unwrap(), Some((1, p1.clone())));
+        assert_eq!(c1.recv(&mut buff).unwrap(), None);
+    }
+}
@@ -0,0 +1,94 @@
+//! Duplicate suppression for received containers, dropping repeated
+//! deliveries (ie. via broadcast and relays) before parsing and dispatch.
+
+use core::convert::TryFrom;
+
+use dsf_core::prelude::*;
+use dsf_core::types::SIGNATURE_LEN;
+
+/// Default number of recent signatures tracked for duplicate suppression
+pub const DEDUP_CACHE_LEN: usize = 32;
+
+/// Bounded cache of recently received container signatures
+#[derive(Debug)]
+pub struct DedupCache<const M: usize = DEDUP_CACHE_LEN> {
+    /// Recently received (signature, time) entries, expired by time
+    seen: heapless::Vec<(Signature, u64), M>,
+}
+
+impl <const M: usize> Default for DedupCache<M> {
+    fn default() -> Self {
+        Self { seen: heapless::Vec::new() }
+    }
+}
+
+impl <const M: usize> DedupCache<M> {
+    /// Fetch the signature identifying an encoded container
+    ///
+    /// Containers end with their signature, so duplicates are identified without parsing.
+    pub fn signature(data: &[u8]) -> Option<Signature> {
+        if data.len() < SIGNATURE_LEN {
+            return None;
+        }
+
+        Signature::try_from(&data[data.len() - SIGNATURE_LEN..]).ok()
+    }
+
+    /// Check whether a container signature has been recorded within `window` ms
+    pub fn check(&mut self, sig: &Signature, now: u64, window: u64) -> bool {
+        // Drop expired signatures
+        self.seen.retain(|(_s, t)| now.saturating_sub(*t) < window);
+
+        self.seen.iter().any(|(s, _t)| s == sig)
+    }
+
+    /// Record a container signature, this should only be called once the
+    /// container has been parsed and the signature verified
+    pub fn insert(&mut self, sig: Signature, now: u64) {
+        if self.seen.iter().any(|(s, _t)| s == &sig) {
+            return;
+        }
+
+        // Evict the oldest entry where full
+        if self.seen.is_full() {
+            let oldest = self.seen.iter().enumerate()
+                .min_by_key(|(_n, (_s, t))| *t)
+                .map(|(n, _v)| n);
+            if let Some(n) = oldest {
+                self.seen.swap_remove(n);
+            }
+        }
+        let _ = self.seen.push((sig, now));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dedup_window() {
+        let mut c = DedupCache::<2>::default();
+        let sig = |b: u8| DedupCache::<2>::signature(&[b; 80]).unwrap();
+        let (a, b, d) = (sig(0x11), sig(0x22), sig(0x33));
+
+        // Signatures are only duplicates once recorded
+        assert!(!c.check(&a, 0, 100));
+        assert!(!c.check(&a, 5, 100));
+        c.insert(a.clone(), 5);
+        assert!(c.check(&a, 10, 100));
+
+        // Repeats after the window are not
+        assert!(!c.check(&a, 200, 100));
+
+        // Oldest entries are evicted when full
+        c.insert(b.clone(), 210);
+        c.insert(d.clone(), 220);
+        c.insert(a.clone(), 230);
+        assert!(!c.check(&b, 240, 100));
+        assert!(c.check(&d, 240, 100));
+
+        // Short buffers are not tracked
+        assert_eq!(DedupCache::<2>::signature(&[0x11; 4]), None);
+    }
+}
@@ -0,0 +1,87 @@
+//! Discovery sessions, collecting responses to a discovery request
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use dsf_core::prelude::*;
+
+use super::DiscoverQuery;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// Service discovered in response to a discovery request
+#[derive(Clone, PartialEq, Debug)]
+pub struct Discovered<Info, Addr> {
+    /// Service ID
+    pub id: Id,
+    /// Decoded service information
+    pub info: Info,
+    /// Address the service responded from
+    pub addr: Addr,
+}
+
+/// Discovery session, collecting de-duplicated results until the deadline
+#[derive(Clone, PartialEq, Debug)]
+pub struct DiscoverySession<Info, Addr> {
+    /// Request ID for the discovery request
+    pub req_id: u16,
+    /// Discovery query, used to filter responses
+    pub(crate) query: DiscoverQuery,
+    /// Time (ms) at which the session completes
+    pub(crate) deadline: u64,
+    /// Indicates completion has been reported
+    pub(crate) done: bool,
+    /// Discovered services
+    pub(crate) results: Vec<Discovered<Info, Addr>>,
+}
+
+impl <Info, Addr> DiscoverySession<Info, Addr> {
+    pub(crate) fn new(req_id: u16, query: DiscoverQuery, deadline: u64) -> Self {
+        Self {
+            req_id,
+            query,
+            deadline,
+            done: false,
+            results: Vec::new(),
+        }
+    }
+
+    /// Check whether the session window has elapsed
+    pub fn expired(&self, now: u64) -> bool {
+        now >= self.deadline
+    }
+
+    /// Fetch services discovered so far
+    pub fn results(&self) -> &[Discovered<Info, Addr>] {
+        &self.results
+    }
+
+    /// Add a discovered service, returning false for duplicates
+    pub(crate) fn insert(&mut self, d: Discovered<Info, Addr>) -> bool {
+        if self.results.iter().any(|r| r.id == d.id) {
+            return false;
+        }
+
+        self.results.push(d);
+        true
+    }
+}
+
+/// Encode a discovery request body, prefixed with the requesting application id
+pub fn encode_discover_body(application_id: u16, body: &[u8]) -> Vec<u8> {
+    let mut b = Vec::with_capacity(2 + body.len());
+
+    b.extend_from_slice(&application_id.to_le_bytes());
+    b.extend_from_slice(body);
+
+    b
+}
+
+/// Decode a discovery request body, returning the application id and application body
+pub fn decode_discover_body(body: &[u8]) -> Option<(u16, &[u8])> {
+    if body.len() < 2 {
+        return None;
+    }
+
+    Some((LittleEndian::read_u16(&body[..2]), &body[2..]))
+}
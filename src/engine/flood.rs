@@ -0,0 +1,192 @@
+//! Discovery flood protection, rate limiting and delaying discovery responses
+
+use dsf_core::prelude::*;
+
+/// Default number of sources and pending responses tracked
+pub const DISCOVER_LIMIT_LEN: usize = 16;
+
+/// Discovery response limiter
+#[derive(Debug)]
+pub struct DiscoverLimiter<Addr, const M: usize = DISCOVER_LIMIT_LEN> {
+    /// Last response time for each source
+    sources: heapless::Vec<(Id, u64), M>,
+    /// Global window start time and response count
+    window: (u64, u16),
+    /// Pending responses (address, page signature, due time)
+    pending: heapless::Vec<(Addr, Signature, u64), M>,
+    /// Our primary pages recently heard from other nodes (page signature, time heard)
+    heard: heapless::Vec<(Signature, u64), M>,
+    /// PRNG state for response jitter
+    rng: u32,
+}
+
+impl <Addr: Clone + PartialEq, const M: usize> DiscoverLimiter<Addr, M> {
+    /// Create a new limiter, seeding response jitter from the provided bytes
+    pub fn new(seed: &[u8]) -> Self {
+        let rng = seed.iter().fold(0x9e37_79b9u32, |a, b| a.rotate_left(5) ^ *b as u32);
+
+        Self {
+            sources: heapless::Vec::new(),
+            window: (0, 0),
+            pending: heapless::Vec::new(),
+            heard: heapless::Vec::new(),
+            rng: rng | 1,
+        }
+    }
+
+    /// Check whether a response to `source` is permitted by per-source and global limits,
+    /// recording the response if so
+    pub fn permit(&mut self, source: &Id, now: u64, source_interval: u64, limit: u16, window: u64) -> bool {
+        // Check per-source interval
+        let last = self.sources.iter_mut().find(|(id, _t)| id == source);
+        if let Some((_id, t)) = &last {
+            if now.saturating_sub(*t) < source_interval {
+                return false;
+            }
+        }
+
+        // Check global limit
+        if now.saturating_sub(self.window.0) >= window {
+            self.window = (now, 0);
+        }
+        if limit > 0 && self.window.1 >= limit {
+            return false;
+        }
+
+        // Record response
+        self.window.1 += 1;
+        match last {
+            Some((_id, t)) => *t = now,
+            None => {
+                if self.sources.is_full() {
+                    let oldest = self.sources.iter().enumerate()
+                        .min_by_key(|(_n, (_id, t))| *t)
+                        .map(|(n, _v)| n);
+                    if let Some(n) = oldest {
+                        self.sources.swap_remove(n);
+                    }
+                }
+                let _ = self.sources.push((source.clone(), now));
+            }
+        }
+
+        true
+    }
+
+    /// Schedule a response with the page `sig` and a random delay of up to `jitter` ms,
+    /// returning false if the response is already pending for the address
+    pub fn schedule(&mut self, to: Addr, sig: Signature, now: u64, jitter: u64) -> bool {
+        if self.pending.iter().any(|(a, s, _t)| a == &to && s == &sig) {
+            return false;
+        }
+
+        let due = now + self.next_rand() as u64 % (jitter + 1);
+
+        self.pending.push((to, sig, due)).is_ok()
+    }
+
+    /// Record that one of our primary pages `sig` was heard from another node,
+    /// ie. another node has answered a discovery request with the same page.
+    /// Pending responses with this page are dropped.
+    pub fn heard(&mut self, sig: &Signature, now: u64) {
+        self.pending.retain(|(_a, s, _t)| s != sig);
+
+        match self.heard.iter_mut().find(|(s, _t)| s == sig) {
+            Some((_s, t)) => *t = now,
+            None => {
+                if self.heard.is_full() {
+                    let oldest = self.heard.iter().enumerate()
+                        .min_by_key(|(_n, (_s, t))| *t)
+                        .map(|(n, _v)| n);
+                    if let Some(n) = oldest {
+                        self.heard.swap_remove(n);
+                    }
+                }
+                let _ = self.heard.push((sig.clone(), now));
+            }
+        }
+    }
+
+    /// Check whether responses with the page `sig` are suppressed, due to the
+    /// same page being heard from another node recently
+    pub fn suppressed(&self, sig: &Signature, now: u64, suppress: u64) -> bool {
+        self.heard.iter().any(|(s, t)| s == sig && now.saturating_sub(*t) < suppress)
+    }
+
+    /// Fetch the next due response, dropping responses while suppressed
+    pub fn due(&mut self, now: u64, suppress: u64) -> Option<(Addr, Signature)> {
+        while let Some(index) = self.pending.iter().position(|(_a, _s, t)| *t <= now) {
+            let (to, sig, _t) = self.pending.swap_remove(index);
+
+            if !self.suppressed(&sig, now, suppress) {
+                return Some((to, sig));
+            }
+        }
+
+        None
+    }
+
+    /// xorshift32 PRNG
+    fn next_rand(&mut self) -> u32 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng = x;
+        x
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limit_source() {
+        let mut l = DiscoverLimiter::<u8>::new(&[0xaa]);
+        let (a, b) = (Id::from([1u8; 32]), Id::from([2u8; 32]));
+
+        assert_eq!(l.permit(&a, 0, 100, 0, 1000), true);
+        assert_eq!(l.permit(&a, 50, 100, 0, 1000), false);
+        assert_eq!(l.permit(&b, 50, 100, 0, 1000), true);
+        assert_eq!(l.permit(&a, 100, 100, 0, 1000), true);
+    }
+
+    #[test]
+    fn limit_global() {
+        let mut l = DiscoverLimiter::<u8>::new(&[0xaa]);
+
+        for i in 0..4u8 {
+            assert_eq!(l.permit(&Id::from([i; 32]), 10, 0, 4, 1000), true);
+        }
+        assert_eq!(l.permit(&Id::from([5u8; 32]), 20, 0, 4, 1000), false);
+
+        // Limit resets with window
+        assert_eq!(l.permit(&Id::from([5u8; 32]), 1010, 0, 4, 1000), true);
+    }
+
+    #[test]
+    fn delay_and_suppress() {
+        let mut l = DiscoverLimiter::<u8>::new(&[0xaa]);
+        let sig = Signature::from([0xbb; 64]);
+
+        // Responses are delayed by up to the jitter period, once per address
+        assert_eq!(l.schedule(1, sig.clone(), 0, 100), true);
+        assert_eq!(l.schedule(1, sig.clone(), 0, 100), false);
+        assert_eq!(l.due(101, 0), Some((1, sig.clone())));
+        assert_eq!(l.due(101, 0), None);
+
+        // Pending responses are dropped when the same page is heard from another node
+        let other = Signature::from([0xcc; 64]);
+        assert_eq!(l.schedule(2, sig.clone(), 200, 100), true);
+        assert_eq!(l.schedule(2, other.clone(), 200, 100), true);
+        l.heard(&sig, 250);
+        assert_eq!(l.suppressed(&sig, 260, 100), true);
+        assert_eq!(l.suppressed(&other, 260, 100), false);
+
+        // Responses with other pages are unaffected
+        assert_eq!(l.due(301, 100), Some((2, other)));
+        assert_eq!(l.due(301, 100), None);
+        assert_eq!(l.suppressed(&sig, 400, 100), false);
+    }
+}
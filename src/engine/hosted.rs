@@ -0,0 +1,58 @@
+//! Additional services hosted by an engine, sharing the engine comms and store
+
+use dsf_core::prelude::*;
+
+use crate::store::ObjectInfo;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// Service hosted alongside the engine primary service
+///
+/// Hosted service keys, the last published object and subscribers are persisted
+/// in the engine store (see [crate::store::Store::get_hosted]), separate from peers.
+#[derive(Debug)]
+pub struct HostedService<Info> {
+    /// Hosted service instance
+    pub(crate) svc: Service<Info>,
+    /// Signature of the current primary page
+    pub(crate) pri: Signature,
+    /// Last published object
+    pub(crate) last: ObjectInfo,
+    /// Subscribers to the hosted service
+    pub(crate) subscribers: Vec<Id>,
+}
+
+impl <Info> HostedService<Info> {
+    /// Fetch the hosted service ID
+    pub fn id(&self) -> Id {
+        self.svc.id()
+    }
+
+    /// Fetch the current primary page signature
+    pub fn primary(&self) -> &Signature {
+        &self.pri
+    }
+
+    /// Fetch subscribers to the hosted service
+    pub fn subscribers(&self) -> &[Id] {
+        &self.subscribers
+    }
+
+    /// Add a subscriber, returning false if already subscribed
+    pub(crate) fn subscribe(&mut self, id: &Id) -> bool {
+        if self.subscribers.contains(id) {
+            return false;
+        }
+
+        self.subscribers.push(id.clone());
+        true
+    }
+
+    /// Remove a subscriber, returning false if not subscribed
+    pub(crate) fn unsubscribe(&mut self, id: &Id) -> bool {
+        let n = self.subscribers.len();
+        self.subscribers.retain(|s| s != id);
+        n != self.subscribers.len()
+    }
+}
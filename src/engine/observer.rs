@@ -0,0 +1,55 @@
+//! Engine observers, notified of significant internal events for auditing
+//! and application hooks that are not surfaced via [super::EngineEvent]
+
+use core::fmt::Debug;
+
+use dsf_core::prelude::*;
+
+use super::AccessKind;
+
+/// Reasons for received objects being dropped
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DropReason {
+    /// Duplicate container received within the duplicate suppression window
+    Duplicate,
+    /// Replayed object
+    Replay,
+    /// Own object received (ie. via broadcast)
+    Own,
+    /// Operation rejected by the access policy
+    Denied(AccessKind),
+}
+
+/// Observer trait, called by the engine on internal state changes
+///
+/// All methods default to no-ops so implementations need only handle events of interest.
+pub trait EngineObserver<Addr: Clone + Debug> {
+    /// Called when a peer is updated following an authenticated request or response
+    fn peer_updated(&mut self, _id: &Id, _addr: &Addr) {}
+
+    /// Called when a new or changed public key is stored for a peer,
+    /// `symmetric` indicates whether session keys were derived
+    fn keys_exchanged(&mut self, _id: &Id, _symmetric: bool) {}
+
+    /// Called when the address of a known peer changes
+    fn address_changed(&mut self, _id: &Id, _from: &Addr, _to: &Addr) {}
+
+    /// Called when a round trip time is measured for a peer
+    fn rtt_updated(&mut self, _id: &Id, _rtt_ms: u64) {}
+
+    /// Called when a received page or data object is written to the store
+    fn page_stored(&mut self, _id: &Id, _sig: &Signature) {}
+
+    /// Called when data is published by the engine or a hosted service
+    fn published(&mut self, _id: &Id, _sig: &Signature) {}
+
+    /// Called when a page is sent in response to a discovery request
+    fn discover_response(&mut self, _to: &Addr, _sig: &Signature) {}
+
+    /// Called when a received object is dropped
+    fn dropped(&mut self, _reason: DropReason, _from: &Addr) {}
+}
+
+/// No-op observer
+impl <Addr: Clone + Debug> EngineObserver<Addr> for () {}
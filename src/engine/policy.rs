@@ -0,0 +1,93 @@
+//! Access policies, used by the engine to authorise peer operations
+
+use core::fmt::Debug;
+
+use dsf_core::prelude::*;
+
+use crate::store::{Peer, Access};
+
+/// Operations subject to access control
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AccessKind {
+    /// Subscription requests from a peer
+    Subscribe,
+    /// Service information queries from a peer
+    Query,
+    /// Discovery requests from a peer
+    Discover,
+    /// Pages or data received from a peer
+    Data,
+}
+
+/// Access policy trait, consulted by the engine prior to handling peer operations
+pub trait AccessPolicy<Addr: Clone + Debug> {
+    /// Check whether a peer is permitted to perform the specified operation,
+    /// `peer` contains stored information (and keys) for the peer where available
+    fn allowed(&self, kind: AccessKind, id: &Id, addr: &Addr, peer: Option<&Peer<Addr>>) -> bool;
+}
+
+/// Built-in allow / deny list policy, using [Peer::access] entries from the [crate::store::Store]
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AccessList {
+    /// Permit peers without an explicit [Access] entry
+    pub default_allow: bool,
+}
+
+impl AccessList {
+    /// Create an access list permitting only explicitly allowed peers
+    pub fn allowlist() -> Self {
+        Self { default_allow: false }
+    }
+
+    /// Create an access list permitting all but explicitly denied peers
+    pub fn denylist() -> Self {
+        Self { default_allow: true }
+    }
+}
+
+impl Default for AccessList {
+    fn default() -> Self {
+        Self::denylist()
+    }
+}
+
+impl <Addr: Clone + Debug> AccessPolicy<Addr> for AccessList {
+    fn allowed(&self, _kind: AccessKind, _id: &Id, _addr: &Addr, peer: Option<&Peer<Addr>>) -> bool {
+        match peer.map(|p| p.access) {
+            Some(Access::Allow) => true,
+            Some(Access::Deny) => false,
+            _ => self.default_allow,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn access_list() {
+        let id = Id::default();
+        let allowed = Peer::<u8>{ access: Access::Allow, ..Default::default() };
+        let denied = Peer::<u8>{ access: Access::Deny, ..Default::default() };
+        let unknown = Peer::<u8>::default();
+
+        let tests = [
+            (AccessList::denylist(), None, true),
+            (AccessList::denylist(), Some(&unknown), true),
+            (AccessList::denylist(), Some(&allowed), true),
+            (AccessList::denylist(), Some(&denied), false),
+            (AccessList::allowlist(), None, false),
+            (AccessList::allowlist(), Some(&unknown), false),
+            (AccessList::allowlist(), Some(&allowed), true),
+            (AccessList::allowlist(), Some(&denied), false),
+        ];
+
+        for (policy, peer, expected) in &tests {
+            assert_eq!(policy.allowed(AccessKind::Subscribe, &id, &1, *peer), *expected,
+                "Unexpected result for policy: {:?} peer: {:?}", policy, peer);
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//! Discovery queries, combining option and body predicates
+//!
+//! Queries are encoded into the body of a discovery request, with option
+//! predicates referencing the options attached to the request by index.
+
+use core::convert::TryFrom;
+
+use dsf_core::options::Options;
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec::Vec, boxed::Box};
+
+use super::Filter;
+
+/// Maximum nesting depth for decoded queries
+pub const MAX_QUERY_DEPTH: usize = 8;
+
+const QUERY_ANY: u8 = 0x00;
+const QUERY_OPTION: u8 = 0x01;
+const QUERY_BODY: u8 = 0x02;
+const QUERY_AND: u8 = 0x03;
+const QUERY_OR: u8 = 0x04;
+const QUERY_NOT: u8 = 0x05;
+
+/// Errors encoding a [DiscoverQuery] exceeding wire format limits
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum QueryError {
+    /// More than 256 distinct options referenced
+    TooManyOptions,
+    /// Body predicate longer than 65535 bytes
+    BodyTooLong,
+    /// More than 255 sub-queries in an And or Or expression
+    TooManyTerms,
+    /// Query nested deeper than [MAX_QUERY_DEPTH]
+    TooDeep,
+}
+
+/// Discovery query expression
+#[derive(Clone, PartialEq, Debug)]
+pub enum DiscoverQuery {
+    /// Match any service
+    Any,
+    /// Match services with the specified public option (name, kind, location, etc.)
+    HasOption(Options),
+    /// Match service information using [dsf_core::api::Application::matches]
+    Body(Vec<u8>),
+    /// Match services matching all sub-queries
+    And(Vec<DiscoverQuery>),
+    /// Match services matching any sub-query
+    Or(Vec<DiscoverQuery>),
+    /// Match services not matching the sub-query
+    Not(Box<DiscoverQuery>),
+}
+
+/// Service attributes evaluated by a [DiscoverQuery]
+pub struct QueryTarget<'a, F: Fn(&[u8]) -> bool> {
+    /// Public options for the service
+    pub options: &'a [Options],
+    /// Predicate for matching service information
+    pub body: F,
+}
+
+impl <'a, 'b, F: Fn(&[u8]) -> bool> Filter<&'b QueryTarget<'a, F>> for DiscoverQuery {
+    fn matches(&self, t: &'b QueryTarget<'a, F>) -> bool {
+        use DiscoverQuery::*;
+
+        match self {
+            Any => true,
+            HasOption(o) => Filter::matches(&t.options, o.clone()),
+            Body(b) => (t.body)(b),
+            And(q) => q.iter().all(|q| Filter::matches(q, t)),
+            Or(q) => q.iter().any(|q| Filter::matches(q, t)),
+            Not(q) => !Filter::matches(q.as_ref(), t),
+        }
+    }
+}
+
+impl DiscoverQuery {
+    /// Build a query matching the service body and all provided options
+    pub fn all(body: &[u8], options: &[Options]) -> Self {
+        let mut q = Vec::with_capacity(options.len() + 1);
+
+        if !body.is_empty() {
+            q.push(DiscoverQuery::Body(body.to_vec()));
+        }
+        q.extend(options.iter().map(|o| DiscoverQuery::HasOption(o.clone())));
+
+        DiscoverQuery::And(q)
+    }
+
+    /// Encode a query, appending referenced options to `options`
+    ///
+    /// Queries exceeding the limits of the wire format return an error,
+    /// in which case the contents of `buff` and `options` are unspecified
+    pub fn encode(&self, buff: &mut Vec<u8>, options: &mut Vec<Options>) -> Result<(), QueryError> {
+        self.encode_inner(buff, options, 0)
+    }
+
+    fn encode_inner(&self, buff: &mut Vec<u8>, options: &mut Vec<Options>, depth: usize) -> Result<(), QueryError> {
+        use DiscoverQuery::*;
+
+        if depth > MAX_QUERY_DEPTH {
+            return Err(QueryError::TooDeep);
+        }
+
+        match self {
+            Any => buff.push(QUERY_ANY),
+            HasOption(o) => {
+                let index = match options.iter().position(|v| v == o) {
+                    Some(i) => i,
+                    None => {
+                        options.push(o.clone());
+                        options.len() - 1
+                    }
+                };
+
+                buff.push(QUERY_OPTION);
+                buff.push(u8::try_from(index).map_err(|_| QueryError::TooManyOptions)?);
+            },
+            Body(b) => {
+                let n = u16::try_from(b.len()).map_err(|_| QueryError::BodyTooLong)?;
+
+                buff.push(QUERY_BODY);
+                buff.extend_from_slice(&n.to_le_bytes());
+                buff.extend_from_slice(b);
+            },
+            And(q) | Or(q) => {
+                let n = u8::try_from(q.len()).map_err(|_| QueryError::TooManyTerms)?;
+
+                buff.push(if let And(_) = self { QUERY_AND } else { QUERY_OR });
+                buff.push(n);
+                for v in q {
+                    v.encode_inner(buff, options, depth + 1)?;
+                }
+            },
+            Not(q) => {
+                buff.push(QUERY_NOT);
+                q.encode_inner(buff, options, depth + 1)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Decode a query from a discovery request body, an empty body matches any service
+    pub fn decode(buff: &[u8], options: &[Options]) -> Option<Self> {
+        if buff.is_empty() {
+            return Some(DiscoverQuery::Any);
+        }
+
+        match Self::decode_inner(buff, options, 0)? {
+            (q, n) if n == buff.len() => Some(q),
+            _ => None,
+        }
+    }
+
+    fn decode_inner(buff: &[u8], options: &[Options], depth: usize) -> Option<(Self, usize)> {
+        if depth > MAX_QUERY_DEPTH {
+            return None;
+        }
+
+        let q = match *buff.get(0)? {
+            QUERY_ANY => (DiscoverQuery::Any, 1),
+            QUERY_OPTION => {
+                let o = options.get(*buff.get(1)? as usize)?;
+                (DiscoverQuery::HasOption(o.clone()), 2)
+            },
+            QUERY_BODY => {
+                let n = u16::from_le_bytes([*buff.get(1)?, *buff.get(2)?]) as usize;
+                (DiscoverQuery::Body(buff.get(3..3 + n)?.to_vec()), 3 + n)
+            },
+            k @ (QUERY_AND | QUERY_OR) => {
+                let count = *buff.get(1)? as usize;
+                let mut index = 2;
+                let mut q = Vec::with_capacity(count);
+
+                for _i in 0..count {
+                    let (v, n) = Self::decode_inner(buff.get(index..)?, options, depth + 1)?;
+                    q.push(v);
+                    index += n;
+                }
+
+                match k {
+                    QUERY_AND => (DiscoverQuery::And(q), index),
+                    _ => (DiscoverQuery::Or(q), index),
+                }
+            },
+            QUERY_NOT => {
+                let (v, n) = Self::decode_inner(buff.get(1..)?, options, depth + 1)?;
+                (DiscoverQuery::Not(Box::new(v)), 1 + n)
+            },
+            _ => return None,
+        };
+
+        Some(q)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn query_matches() {
+        let opts = [Options::name("sensor"), Options::kind("temperature")];
+        let t = QueryTarget{ options: &opts, body: |b: &[u8]| *b == [0xaa] };
+
+        let tests = [
+            (DiscoverQuery::Any, true),
+            (DiscoverQuery::HasOption(Options::name("sensor")), true),
+            (DiscoverQuery::HasOption(Options::name("other")), false),
+            (DiscoverQuery::Body(vec![0xaa]), true),
+            (DiscoverQuery::Body(vec![0xbb]), false),
+            (DiscoverQuery::all(&[0xaa], &[Options::name("sensor"), Options::kind("temperature")]), true),
+            (DiscoverQuery::all(&[], &[Options::name("sensor"), Options::kind("humidity")]), false),
+            (DiscoverQuery::Or(vec![
+                DiscoverQuery::HasOption(Options::kind("humidity")),
+                DiscoverQuery::HasOption(Options::kind("temperature")),
+            ]), true),
+            (DiscoverQuery::Not(Box::new(DiscoverQuery::HasOption(Options::name("sensor")))), false),
+        ];
+
+        for (q, expected) in &tests {
+            assert_eq!(Filter::matches(q, &t), *expected, "Unexpected result for query: {:?}", q);
+        }
+    }
+
+    #[test]
+    fn query_encode_decode() {
+        let q = DiscoverQuery::And(vec![
+            DiscoverQuery::Body(vec![0x11, 0x22]),
+            DiscoverQuery::Or(vec![
+                DiscoverQuery::HasOption(Options::name("sensor")),
+                DiscoverQuery::Not(Box::new(DiscoverQuery::HasOption(Options::kind("humidity")))),
+            ]),
+            DiscoverQuery::HasOption(Options::name("sensor")),
+            DiscoverQuery::Any,
+        ]);
+
+        let (mut buff, mut opts) = (Vec::new(), Vec::new());
+        q.encode(&mut buff, &mut opts).expect("Failed to encode query");
+
+        // Options are de-duplicated
+        assert_eq!(opts, vec![Options::name("sensor"), Options::kind("humidity")]);
+
+        let decoded = DiscoverQuery::decode(&buff, &opts).expect("Failed to decode query");
+        assert_eq!(decoded, q);
+
+        // Truncated and invalid queries are rejected
+        assert_eq!(DiscoverQuery::decode(&buff[..buff.len() - 1], &opts), None);
+        assert_eq!(DiscoverQuery::decode(&buff, &opts[..1]), None);
+    }
+
+    #[test]
+    fn query_encode_limits() {
+        let (mut buff, mut opts) = (Vec::new(), Vec::new());
+
+        let q = DiscoverQuery::Body(vec![0u8; u16::MAX as usize + 1]);
+        assert_eq!(q.encode(&mut buff, &mut opts), Err(QueryError::BodyTooLong));
+
+        let q = DiscoverQuery::Or(vec![DiscoverQuery::Any; 256]);
+        assert_eq!(q.encode(&mut buff, &mut opts), Err(QueryError::TooManyTerms));
+
+        let opt = |i: usize| DiscoverQuery::HasOption(Options::name(&format!("{}", i)));
+        let q = DiscoverQuery::And(vec![
+            DiscoverQuery::Or((0..200).map(opt).collect()),
+            DiscoverQuery::Or((200..257).map(opt).collect()),
+        ]);
+        assert_eq!(q.encode(&mut buff, &mut opts), Err(QueryError::TooManyOptions));
+
+        let q = (0..=MAX_QUERY_DEPTH).fold(DiscoverQuery::Any, |q, _| DiscoverQuery::Not(Box::new(q)));
+        assert_eq!(q.encode(&mut buff, &mut opts), Err(QueryError::TooDeep));
+
+        // Queries at the depth limit are encoded and decoded
+        let q = (0..MAX_QUERY_DEPTH).fold(DiscoverQuery::Any, |q, _| DiscoverQuery::Not(Box::new(q)));
+        let (mut buff, mut opts) = (Vec::new(), Vec::new());
+        q.encode(&mut buff, &mut opts).expect("Failed to encode query");
+        assert_eq!(DiscoverQuery::decode(&buff, &opts), Some(q));
+    }
+}
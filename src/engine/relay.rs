@@ -0,0 +1,43 @@
+//! Relay state, tracking downstream subscribers for services relayed by the engine
+
+use dsf_core::prelude::*;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// Service relayed by the engine to downstream subscribers
+///
+/// Relayed pages are forwarded unchanged, so remain signed by (and are validated
+/// against) the original publisher's keys.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RelayedService {
+    /// Relayed service ID
+    pub id: Id,
+    /// Signature of the latest primary page received for the service
+    pub primary: Option<Signature>,
+    /// Downstream subscribers to the relayed service
+    pub subscribers: Vec<Id>,
+}
+
+impl RelayedService {
+    pub(crate) fn new(id: Id) -> Self {
+        Self { id, primary: None, subscribers: Vec::new() }
+    }
+
+    /// Add a downstream subscriber, returning false if already subscribed
+    pub(crate) fn subscribe(&mut self, id: &Id) -> bool {
+        if self.subscribers.contains(id) {
+            return false;
+        }
+
+        self.subscribers.push(id.clone());
+        true
+    }
+
+    /// Remove a downstream subscriber, returning false if not subscribed
+    pub(crate) fn unsubscribe(&mut self, id: &Id) -> bool {
+        let n = self.subscribers.len();
+        self.subscribers.retain(|s| s != id);
+        n != self.subscribers.len()
+    }
+}
@@ -0,0 +1,120 @@
+//! Replay protection for received objects, tracking recently seen
+//! signatures and the latest data index for each peer.
+
+use dsf_core::prelude::*;
+
+/// Default number of recent objects tracked for replay detection
+pub const REPLAY_CACHE_LEN: usize = 32;
+
+/// Bounded cache of recently seen objects
+#[derive(Debug)]
+pub struct ReplayCache<const M: usize = REPLAY_CACHE_LEN> {
+    /// Recently seen (peer, signature, time) entries, expired by time
+    seen: heapless::Vec<(Id, Signature, u64), M>,
+    /// Latest data index for each peer, expired by time and evicted by age when full
+    data: heapless::Vec<(Id, u16, u64), M>,
+}
+
+impl <const M: usize> Default for ReplayCache<M> {
+    fn default() -> Self {
+        Self {
+            seen: heapless::Vec::new(),
+            data: heapless::Vec::new(),
+        }
+    }
+}
+
+impl <const M: usize> ReplayCache<M> {
+    /// Check whether an object is a replay
+    ///
+    /// Objects are replays if the same signature has been seen from the peer
+    /// within `expiry` ms, or if a data index is not newer than the last seen
+    /// within `expiry` ms. Indices are compared over a wrapping window, so
+    /// publishers may wrap past `u16::MAX`, and restarted publishers are accepted
+    /// once the previous index expires.
+    pub fn check(&mut self, id: &Id, sig: &Signature, data_index: Option<u16>, now: u64, expiry: u64) -> bool {
+        // Drop expired entries
+        self.seen.retain(|(_i, _s, t)| now.saturating_sub(*t) < expiry);
+        self.data.retain(|(_i, _n, t)| now.saturating_sub(*t) < expiry);
+
+        // Check for recently seen signatures
+        if self.seen.iter().any(|(i, s, _t)| i == id && s == sig) {
+            return true;
+        }
+
+        // Check data indices are increasing
+        match (data_index, self.data.iter().find(|(i, _n, _t)| i == id)) {
+            (Some(index), Some((_i, n, _t))) => !newer(index, *n),
+            _ => false,
+        }
+    }
+
+    /// Record a received object, this should only be called once the object
+    /// has been successfully handled so failed deliveries may be retried
+    pub fn record(&mut self, id: &Id, sig: &Signature, data_index: Option<u16>, now: u64) {
+        if let Some(index) = data_index {
+            match self.data.iter_mut().find(|(i, _n, _t)| i == id) {
+                Some((_i, n, t)) => {
+                    *n = index;
+                    *t = now;
+                },
+                None => {
+                    Self::evict(&mut self.data, |d| d.2);
+                    let _ = self.data.push((id.clone(), index, now));
+                },
+            }
+        }
+
+        Self::evict(&mut self.seen, |s| s.2);
+        let _ = self.seen.push((id.clone(), sig.clone(), now));
+    }
+
+    /// Remove the oldest entry from a full list
+    fn evict<T, F: Fn(&T) -> u64>(list: &mut heapless::Vec<T, M>, at: F) {
+        if !list.is_full() {
+            return;
+        }
+
+        let oldest = list.iter().enumerate()
+            .min_by_key(|(_n, v)| at(v))
+            .map(|(n, _v)| n);
+
+        if let Some(n) = oldest {
+            list.swap_remove(n);
+        }
+    }
+}
+
+/// Check whether index `a` is newer than `b`, over a wrapping window of half the index space
+fn newer(a: u16, b: u16) -> bool {
+    (a.wrapping_sub(b) as i16) > 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replay_data_index() {
+        let mut c = ReplayCache::<4>::default();
+        let id = Id::from([0x11u8; 32]);
+        let sig = |b: u8| Signature::from([b; 64]);
+
+        // Older and equal indices are replays
+        assert!(!c.check(&id, &sig(1), Some(10), 0, 100));
+        c.record(&id, &sig(1), Some(10), 0);
+        assert!(c.check(&id, &sig(2), Some(9), 10, 100));
+        assert!(c.check(&id, &sig(2), Some(10), 10, 100));
+        assert!(!c.check(&id, &sig(2), Some(11), 10, 100));
+
+        // Indices wrap past u16::MAX
+        c.record(&id, &sig(3), Some(u16::MAX), 20);
+        assert!(!c.check(&id, &sig(4), Some(0), 30, 100));
+        c.record(&id, &sig(4), Some(0), 30);
+        assert!(c.check(&id, &sig(5), Some(u16::MAX - 1), 40, 100));
+
+        // Restarted publishers are accepted once the last index expires
+        assert!(c.check(&id, &sig(6), Some(0), 50, 100));
+        assert!(!c.check(&id, &sig(6), Some(0), 200, 100));
+    }
+}
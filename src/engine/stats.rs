@@ -0,0 +1,142 @@
+//! Engine statistics, counting significant engine activity
+
+use dsf_core::types::BaseKind;
+use dsf_core::wire::Container;
+
+/// Packet counters by object kind
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PacketStats {
+    /// Request messages
+    pub requests: u64,
+    /// Response messages
+    pub responses: u64,
+    /// Service pages
+    pub pages: u64,
+    /// Data objects
+    pub data: u64,
+}
+
+impl PacketStats {
+    /// Count an object of the provided kind
+    pub(crate) fn count(&mut self, kind: BaseKind) {
+        match kind {
+            BaseKind::Request => self.requests += 1,
+            BaseKind::Response => self.responses += 1,
+            BaseKind::Page => self.pages += 1,
+            BaseKind::Block => self.data += 1,
+        }
+    }
+
+    /// Total packets across all kinds
+    pub fn total(&self) -> u64 {
+        self.requests + self.responses + self.pages + self.data
+    }
+}
+
+/// Engine statistics, see [super::Engine::stats]
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EngineStats {
+    /// Objects received and successfully parsed
+    pub rx: PacketStats,
+    /// Objects sent
+    pub tx: PacketStats,
+
+    /// Received objects failing to parse or validate
+    pub parse_errors: u64,
+    /// Store errors encountered while handling messages and updating state
+    pub store_errors: u64,
+
+    /// Duplicate containers dropped before dispatch
+    pub duplicates: u64,
+    /// Replayed objects dropped
+    pub replays: u64,
+    /// Own objects received and dropped (ie. via broadcast)
+    pub own: u64,
+    /// Requests and pages rejected by the access policy
+    pub denied: u64,
+
+    /// Pages sent in response to discovery requests
+    pub discover_responses: u64,
+    /// Data objects published
+    pub published: u64,
+    /// Objects forwarded to subscribers, including relayed pages
+    pub forwarded: u64,
+    /// Events dropped due to a full event queue
+    pub events_dropped: u64,
+
+    /// Current subscribers to the engine's (hosted and relayed) services
+    pub subscribers: u64,
+    /// Current subscriptions to other services
+    pub subscriptions: u64,
+}
+
+impl EngineStats {
+    /// Count an encoded object being sent
+    pub(crate) fn sent(&mut self, data: &[u8]) {
+        let (c, _n) = Container::from(data);
+        self.tx.count(c.header().kind().base());
+    }
+
+    /// Write statistics in the Prometheus text exposition format
+    #[cfg(feature = "prometheus")]
+    pub fn write_prometheus<W: core::fmt::Write>(&self, w: &mut W) -> core::fmt::Result {
+        for (name, help, p) in &[("received", "Objects received", &self.rx), ("sent", "Objects sent", &self.tx)] {
+            writeln!(w, "# HELP dsf_engine_packets_{}_total {} by kind", name, help)?;
+            writeln!(w, "# TYPE dsf_engine_packets_{}_total counter", name)?;
+
+            for (kind, v) in &[("request", p.requests), ("response", p.responses), ("page", p.pages), ("data", p.data)] {
+                writeln!(w, "dsf_engine_packets_{}_total{{kind=\"{}\"}} {}", name, kind, v)?;
+            }
+        }
+
+        let counters = [
+            ("parse_errors", "Received objects failing to parse or validate", self.parse_errors),
+            ("store_errors", "Store errors while handling messages", self.store_errors),
+            ("duplicates", "Duplicate containers dropped", self.duplicates),
+            ("replays", "Replayed objects dropped", self.replays),
+            ("own", "Own objects received and dropped", self.own),
+            ("denied", "Requests and pages rejected by access policy", self.denied),
+            ("discover_responses", "Pages sent in response to discovery", self.discover_responses),
+            ("published", "Data objects published", self.published),
+            ("forwarded", "Objects forwarded to subscribers", self.forwarded),
+            ("events_dropped", "Events dropped due to a full event queue", self.events_dropped),
+        ];
+        for (name, help, v) in &counters {
+            writeln!(w, "# HELP dsf_engine_{}_total {}", name, help)?;
+            writeln!(w, "# TYPE dsf_engine_{}_total counter", name)?;
+            writeln!(w, "dsf_engine_{}_total {}", name, v)?;
+        }
+
+        let gauges = [
+            ("subscribers", "Current subscribers", self.subscribers),
+            ("subscriptions", "Current subscriptions", self.subscriptions),
+        ];
+        for (name, help, v) in &gauges {
+            writeln!(w, "# HELP dsf_engine_{} {}", name, help)?;
+            writeln!(w, "# TYPE dsf_engine_{} gauge", name)?;
+            writeln!(w, "dsf_engine_{} {}", name, v)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "prometheus"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prometheus_text() {
+        let mut s = EngineStats::default();
+        s.rx.count(BaseKind::Block);
+        s.subscribers = 3;
+
+        let mut out = String::new();
+        s.write_prometheus(&mut out).unwrap();
+
+        assert!(out.contains("dsf_engine_packets_received_total{kind=\"data\"} 1\n"));
+        assert!(out.contains("# TYPE dsf_engine_subscribers gauge\ndsf_engine_subscribers 3\n"));
+    }
+}
@@ -1,17 +1,16 @@
 
 use core::fmt::Debug;
 
-use dsf_core::api::Application;
+use dsf_core::{api::Application, options::Options};
 
 use crate::{
-    comms::Comms,
     store::Store,
-    engine::{Engine, EngineEvent},
+    engine::{Engine, EngineEvent, AccessPolicy, EngineObserver, Discovered},
     error::EngineError,
 };
 
 /// A [std::net::UdpSocket] based engine for use with `std`
-impl <A: Application, S: Store<Address=std::net::SocketAddr>, const N: usize> Engine<A, std::net::UdpSocket, S, N> {
+impl <A: Application, S: Store<Address=std::net::SocketAddr>, const N: usize, P: AccessPolicy<std::net::SocketAddr> + Default, O: EngineObserver<std::net::SocketAddr> + Default> Engine<A, std::net::UdpSocket, S, N, P, O> {
     /// Create a new [std::net::UdpSocket] based engine
     pub fn udp<Addr: std::net::ToSocketAddrs + Debug>(info: A::Info, addr: Addr, store: S) -> Result<Self, EngineError<std::io::Error, <S as Store>::Error>> {
         log::debug!("Connecting to socket: {:?}", addr);
@@ -26,19 +25,42 @@ impl <A: Application, S: Store<Address=std::net::SocketAddr>, const N: usize> En
         // Create engine instance
         Self::new(info, comms, store)
     }
+}
 
+impl <A: Application, S: Store<Address=std::net::SocketAddr>, const N: usize, P: AccessPolicy<std::net::SocketAddr>, O: EngineObserver<std::net::SocketAddr>> Engine<A, std::net::UdpSocket, S, N, P, O> {
     /// Tick function to update engine and poll on socket
     pub fn tick(&mut self) -> Result<EngineEvent, EngineError<std::io::Error, <S as Store>::Error>> {
-        let mut buff = [0u8; N];
+        // Poll the socket and update internal state
+        self.update()
+    }
+
+    /// Discover local services, polling the engine until the discovery window elapses
+    ///
+    /// Discovery events are consumed, other events received while waiting remain
+    /// queued for [Engine::poll_event] or subsequent calls to [Engine::tick].
+    pub fn discover_wait(&mut self, body: &[u8], opts: &[Options]) -> Result<Vec<Discovered<A::Info, std::net::SocketAddr>>, EngineError<std::io::Error, <S as Store>::Error>> {
+        let req_id = self.discover(body, opts)?;
+        let deadline = self.time() + self.opts.discover_window_ms;
+
+        let mut held = Vec::new();
+
+        while self.time() < deadline {
+            match self.tick()? {
+                EngineEvent::DiscoverDone(id) if id == req_id => break,
+                EngineEvent::None => std::thread::sleep(std::time::Duration::from_millis(1)),
+                EngineEvent::Discover(_) => (),
+                evt => held.push(evt),
+            }
+        }
 
-        // Check for and handle received messages
-        if let Some((n, a)) = Comms::recv(&mut self.comms, &mut buff).map_err(EngineError::Comms)? {
-            log::debug!("Received {} bytes from {:?}", n, a);
-            return self.handle(a, &mut buff[..n]);
+        // Return held events to the front of the queue, preserving order
+        for evt in held.into_iter().rev() {
+            if self.events.push_front(evt).is_err() {
+                self.stats.events_dropped += 1;
+            }
         }
 
-        // Update internal state
-        return self.update();
+        Ok(self.take_discovered(req_id).unwrap_or_default())
     }
 
     /// Resolve the local address of the engine
@@ -0,0 +1,42 @@
+use core::fmt::Debug;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+
+use dsf_core::api::Application;
+
+use crate::{
+    store::Store,
+    engine::{Engine, AccessPolicy, EngineObserver},
+    error::EngineError,
+};
+
+/// A [std::os::unix::net::UnixDatagram] based engine, for local daemon communication
+impl <A: Application, S: Store<Address=PathBuf>, const N: usize, P: AccessPolicy<PathBuf> + Default, O: EngineObserver<PathBuf> + Default> Engine<A, UnixDatagram, S, N, P, O> {
+    /// Create a new [std::os::unix::net::UnixDatagram] based engine bound to the provided path
+    ///
+    /// Binding fails if the path already exists, stale sockets must be removed by the caller.
+    /// Discovery broadcasts require the socket to be bound within a directory created
+    /// with [crate::comms::unix::broadcast_dir].
+    pub fn unix<T: AsRef<Path> + Debug>(info: A::Info, path: T, store: S) -> Result<Self, EngineError<std::io::Error, <S as Store>::Error>> {
+        log::debug!("Binding to socket: {:?}", path);
+
+        // Attempt to bind unix socket
+        let comms = UnixDatagram::bind(path).map_err(EngineError::Comms)?;
+
+        // Enable nonblocking polling
+        comms.set_nonblocking(true).map_err(EngineError::Comms)?;
+
+        // Create engine instance
+        Self::new(info, comms, store)
+    }
+}
+
+impl <A: Application, S: Store<Address=PathBuf>, const N: usize, P: AccessPolicy<PathBuf>, O: EngineObserver<PathBuf>> Engine<A, UnixDatagram, S, N, P, O> {
+    /// Resolve the local socket path of the engine
+    pub fn path(&mut self) -> Result<PathBuf, EngineError<std::io::Error, <S as Store>::Error>> {
+        let a = self.comms.local_addr().map_err(EngineError::Comms)?;
+
+        a.as_pathname().map(|p| p.to_path_buf())
+            .ok_or_else(|| EngineError::Comms(std::io::Error::new(std::io::ErrorKind::NotConnected, "socket not bound")))
+    }
+}
@@ -26,4 +26,10 @@ pub enum EngineError<CommsError: Debug, StoreError: Debug> {
 
     #[cfg_attr(feature="thiserror", error("Overrun in static vector"))]
     Overrun,
+
+    #[cfg_attr(feature="thiserror", error("Unknown service"))]
+    UnknownService,
+
+    #[cfg_attr(feature="thiserror", error("Invalid query: {0:?}"))]
+    Query(crate::engine::QueryError),
 }
@@ -8,7 +8,8 @@ pub struct MemoryStore<Addr: Clone + Debug = std::net::SocketAddr> {
     pub(crate) our_keys: Option<Keys>,
     pub(crate) last_sig: Option<ObjectInfo>,
     pub(crate) peers: HashMap<Id, Peer<Addr>>,
-    pub(crate) pages: HashMap<Signature, Container>
+    pub(crate) pages: HashMap<Signature, Container>,
+    pub(crate) hosted: HashMap<Id, HostedInfo>,
 }
 
 impl <Addr: Clone + Debug> MemoryStore<Addr> {
@@ -18,8 +19,14 @@ impl <Addr: Clone + Debug> MemoryStore<Addr> {
             last_sig: None,
             peers: HashMap::new(),
             pages: HashMap::new(),
+            hosted: HashMap::new(),
         }
     }
+
+    /// List hosted services with stored state
+    pub fn hosted(&self) -> Vec<Id> {
+        self.hosted.keys().cloned().collect()
+    }
 }
 
 impl <Addr: Clone + Debug + 'static> Store for MemoryStore<Addr> {
@@ -80,6 +87,32 @@ impl <Addr: Clone + Debug + 'static> Store for MemoryStore<Addr> {
             None => Ok(None),
         }
     }
+
+    fn remove_page(&mut self, sig: &Signature) -> Result<(), Self::Error> {
+        self.pages.remove(sig);
+        Ok(())
+    }
+
+    fn visit_pages<F: FnMut(&Signature, Container<&[u8]>)>(&self, mut f: F) -> Result<(), Self::Error> {
+        for (sig, p) in self.pages.iter() {
+            f(sig, Container::from(p.raw()).0);
+        }
+        Ok(())
+    }
+
+    fn get_hosted(&self, id: &Id) -> Result<Option<HostedInfo>, Self::Error> {
+        Ok(self.hosted.get(id).cloned())
+    }
+
+    fn set_hosted(&mut self, id: &Id, info: &HostedInfo) -> Result<(), Self::Error> {
+        self.hosted.insert(id.clone(), info.clone());
+        Ok(())
+    }
+
+    fn remove_hosted(&mut self, id: &Id) -> Result<(), Self::Error> {
+        self.hosted.remove(id);
+        Ok(())
+    }
 }
 
 impl <'a, Addr: Clone + Debug + 'static> IntoIterator for &'a MemoryStore<Addr>{
@@ -11,7 +11,10 @@ use dsf_core::prelude::*;
 use dsf_core::keys::{Keys, KeySource};
 use dsf_core::types::{ImmutableData, SIGNATURE_LEN, MutableData};
 use dsf_core::wire::Container;
-use dsf_core::crypto::{Crypto, PubKey as _};
+use dsf_core::crypto::{Crypto, PubKey as _, Hash as _};
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
 
 
 #[cfg(feature = "std")]
@@ -22,7 +25,10 @@ pub use mem_store::MemoryStore;
 #[cfg(feature = "sled")]
 mod sled_store;
 #[cfg(feature = "sled")]
-pub use sled_store::SledStore;
+pub use sled_store::{SledStore, SledAddress, StoreDump, LastDump, PeerDump, PageDump};
+
+/// Maximum number of candidate addresses tracked per peer
+pub const MAX_ADDR_CANDIDATES: usize = 4;
 
 bitflags::bitflags! {
     /// Features supported by a store interface
@@ -77,6 +83,33 @@ pub trait Store: KeySource {
 
     // Fetch a stored page
     fn fetch_page<T: MutableData>(&mut self, sig: &Signature, buff: T) -> Result<Option<Container<T>>, Self::Error>;
+
+    /// Remove a stored page, stores not supporting removal retain pages
+    fn remove_page(&mut self, _sig: &Signature) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Visit stored pages, used to rebuild engine state on start.
+    /// Stores not supporting iteration visit no pages.
+    fn visit_pages<F: FnMut(&Signature, Container<&[u8]>)>(&self, _f: F) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Fetch state for a hosted service.
+    /// Stores not supporting hosted services do not persist this state.
+    fn get_hosted(&self, _id: &Id) -> Result<Option<HostedInfo>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Update state for a hosted service
+    fn set_hosted(&mut self, _id: &Id, _info: &HostedInfo) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Remove state for a hosted service
+    fn remove_hosted(&mut self, _id: &Id) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -87,6 +120,15 @@ pub struct ObjectInfo {
     pub sig: Signature,
 }
 
+/// Stored state for a service hosted alongside the engine primary service,
+/// kept separate from peers as this includes private keys
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HostedInfo {
+    pub keys: Keys,                     // Hosted service keys
+    pub last: Option<ObjectInfo>,       // Last published object, for continuing the page chain
+    pub subscribers: Vec<Id>,           // Subscribers to the hosted service
+}
+
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Peer<Addr: Clone + Debug> {
@@ -94,6 +136,13 @@ pub struct Peer<Addr: Clone + Debug> {
     pub addr: Option<Addr>,             // Optional address for the peer / service
     pub subscriber: bool,               // Indicate whether this service is subscribed to us
     pub subscribed: SubscribeState,     // Indicate whether we are subscribed to this service
+    pub symmetric: bool,                // Indicate whether this peer supports symmetric mode
+    pub symmetric_failed: bool,         // Symmetric mode requests to this peer went unanswered, not persisted
+    pub access: Access,                 // Access list entry for this peer
+    pub last_seen: Option<u64>,         // Engine time (ms) of last authenticated traffic, not persisted
+    pub rtt: Option<u64>,               // Last measured round trip time (ms), not persisted
+    pub online: bool,                   // Indicate whether this peer is considered reachable
+    pub candidates: [Option<Addr>; MAX_ADDR_CANDIDATES], // Recent authenticated addresses, most recent first, not persisted
 }
 
 impl <Addr: Clone + Debug> Default for Peer<Addr> {
@@ -103,6 +152,13 @@ impl <Addr: Clone + Debug> Default for Peer<Addr> {
             addr: None,
             subscriber: false,
             subscribed: SubscribeState::None,
+            symmetric: false,
+            symmetric_failed: false,
+            access: Access::Default,
+            last_seen: None,
+            rtt: None,
+            online: false,
+            candidates: Default::default(),
         }
     }
 }
@@ -116,6 +172,18 @@ pub enum SubscribeState {
     Unsubscribing(RequestId),
 }
 
+/// Access list entries for peers, see [crate::engine::AccessList]
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Access {
+    /// No explicit entry, use the policy default
+    Default = 0,
+    /// Peer is allowed
+    Allow = 1,
+    /// Peer is denied
+    Deny = 2,
+}
+
 impl <Addr: Clone + Debug> Peer<Addr> {
     pub fn subscribed(&self) -> bool {
         use SubscribeState::*;
@@ -1,6 +1,8 @@
 
 
 use dsf_core::prelude::*;
+use dsf_core::base::HEADER_LEN;
+use dsf_core::types::ID_LEN;
 
 use crate::log::Debug;
 use super::*;
@@ -11,7 +13,73 @@ pub struct SledStore<Addr: Clone + Debug> {
     _addr: PhantomData<Addr>,
 }
 
-impl <Addr: Clone + Debug> SledStore<Addr> {
+/// Address encoding for persisting peers in a [SledStore]
+pub trait SledAddress: Sized {
+    /// Encode address to bytes
+    fn encode(&self) -> Vec<u8>;
+
+    /// Decode address from bytes
+    fn decode(buff: &[u8]) -> Option<Self>;
+}
+
+impl SledAddress for std::net::SocketAddr {
+    fn encode(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+
+    fn decode(buff: &[u8]) -> Option<Self> {
+        core::str::from_utf8(buff).ok()?.parse().ok()
+    }
+}
+
+#[cfg(unix)]
+impl SledAddress for std::path::PathBuf {
+    fn encode(&self) -> Vec<u8> {
+        use std::os::unix::ffi::OsStrExt;
+        self.as_os_str().as_bytes().to_vec()
+    }
+
+    fn decode(buff: &[u8]) -> Option<Self> {
+        use std::os::unix::ffi::OsStrExt;
+        Some(std::ffi::OsStr::from_bytes(buff).into())
+    }
+}
+
+impl <A: SledAddress, B: SledAddress> SledAddress for crate::comms::multi::MultiAddress<A, B> {
+    fn encode(&self) -> Vec<u8> {
+        use crate::comms::multi::MultiAddress::*;
+
+        let (tag, mut d) = match self {
+            First(a) => (0, a.encode()),
+            Second(b) => (1, b.encode()),
+        };
+
+        d.insert(0, tag);
+        d
+    }
+
+    fn decode(buff: &[u8]) -> Option<Self> {
+        use crate::comms::multi::MultiAddress::*;
+
+        match buff.first()? {
+            0 => A::decode(&buff[1..]).map(First),
+            1 => B::decode(&buff[1..]).map(Second),
+            _ => None,
+        }
+    }
+}
+
+impl SledAddress for u8 {
+    fn encode(&self) -> Vec<u8> {
+        vec![*self]
+    }
+
+    fn decode(buff: &[u8]) -> Option<Self> {
+        buff.first().cloned()
+    }
+}
+
+impl <Addr: Clone + Debug + SledAddress> SledStore<Addr> {
     /// Create a new sled-backed store
     pub fn new(path: &str) -> Result<Self, sled::Error> {
         let db = sled::Config::default()
@@ -20,17 +88,214 @@ impl <Addr: Clone + Debug> SledStore<Addr> {
             .flush_every_ms(Some(1_000))
             .open()?;
 
-        let peers = std::collections::HashMap::new();
+        let mut s = Self{db, peers: std::collections::HashMap::new(), _addr: PhantomData};
+
+        // Load persisted peers
+        s.reload()?;
+
+        Ok(s)
+    }
+
+    /// Reload cached peers from storage
+    fn reload(&mut self) -> Result<(), sled::Error> {
+        self.peers.clear();
+
+        for p in self.db.open_tree(SLED_PEER_KEY)?.iter() {
+            let (k, v) = p?;
+
+            match (Id::try_from(k.as_ref()), decode_peer(&v)) {
+                (Ok(id), Some(peer)) => {
+                    self.peers.insert(id, peer);
+                },
+                _ => log::warn!("Failed to decode stored peer: {:02x?}", k.as_ref()),
+            }
+        }
+
+        Ok(())
+    }
 
-        Ok(Self{db, peers, _addr: PhantomData})
+    /// Iterate through stored pages, returning signatures and encoded pages
+    pub fn pages(&self) -> impl Iterator<Item=Result<(Signature, Vec<u8>), sled::Error>> {
+        let pages = self.db.open_tree(SLED_PAGE_KEY);
+
+        pages.into_iter().flat_map(|t| t.iter() ).map(|r| {
+            let (k, v) = r?;
+            let sig = Signature::try_from(k.as_ref()).map_err(|_| {
+                sled::Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid page signature"))
+            })?;
+            Ok((sig, v.to_vec()))
+        })
+    }
+
+    /// List hosted services with stored state
+    pub fn hosted(&self) -> Result<Vec<Id>, sled::Error> {
+        let hosted = self.db.open_tree(SLED_HOSTED_KEY)?;
+
+        hosted.iter().keys()
+            .filter_map(|k| match k {
+                Ok(k) => Id::try_from(k.as_ref()).ok().map(Ok),
+                Err(e) => Some(Err(e)),
+            })
+            .collect()
     }
 }
 
 const SLED_IDENT_KEY: &[u8] = b"ident";
 const SLED_PAGE_KEY: &[u8] = b"page";
 const SLED_LAST_KEY: &[u8] = b"last";
+const SLED_PEER_KEY: &[u8] = b"peer";
+const SLED_HOSTED_KEY: &[u8] = b"hosted";
+
+/// Encode peer information for storage, session keys and private keys are not persisted
+fn encode_peer<Addr: Clone + Debug + SledAddress>(p: &Peer<Addr>) -> Vec<u8> {
+    let mut d = Vec::new();
+
+    d.push(p.subscriber as u8 | (p.symmetric as u8) << 1);
+    d.push(p.access as u8);
+
+    let (state, req_id) = match p.subscribed {
+        SubscribeState::None => (0, 0),
+        SubscribeState::Subscribing(r) => (1, r),
+        SubscribeState::Subscribed => (2, 0),
+        SubscribeState::Unsubscribing(r) => (3, r),
+    };
+    d.push(state);
+    d.extend_from_slice(&req_id.to_le_bytes());
+
+    for f in [p.keys.pub_key.as_deref().map(|k| &k[..]), p.keys.sec_key.as_deref().map(|k| &k[..]), p.addr.as_ref().map(|a| a.encode()).as_deref()] {
+        match f {
+            Some(v) => {
+                d.push(v.len() as u8);
+                d.extend_from_slice(v);
+            },
+            None => d.push(0),
+        }
+    }
+
+    d
+}
+
+/// Decode stored peer information
+fn decode_peer<Addr: Clone + Debug + SledAddress>(d: &[u8]) -> Option<Peer<Addr>> {
+    let mut p = Peer::default();
+
+    let (flags, access, state) = (*d.get(0)?, *d.get(1)?, *d.get(2)?);
+    let req_id = LittleEndian::read_u16(d.get(3..5)?);
+
+    p.subscriber = flags & 0b01 != 0;
+    p.symmetric = flags & 0b10 != 0;
+    p.access = match access {
+        1 => Access::Allow,
+        2 => Access::Deny,
+        _ => Access::Default,
+    };
+    p.subscribed = match state {
+        1 => SubscribeState::Subscribing(req_id),
+        2 => SubscribeState::Subscribed,
+        3 => SubscribeState::Unsubscribing(req_id),
+        _ => SubscribeState::None,
+    };
+
+    // Read length-prefixed fields
+    let mut fields = [None, None, None];
+    let mut i = 5;
+    for f in fields.iter_mut() {
+        let n = *d.get(i)? as usize;
+        if n > 0 {
+            *f = Some(d.get(i+1..i+1+n)?);
+        }
+        i += 1 + n;
+    }
+
+    p.keys.pub_key = fields[0].and_then(|k| PublicKey::try_from(k).ok());
+    p.keys.sec_key = fields[1].and_then(|k| SecretKey::try_from(k).ok());
+    p.addr = fields[2].and_then(Addr::decode);
 
-impl <Addr: Clone + Debug + 'static> Store for SledStore<Addr> {
+    Some(p)
+}
+
+/// Encode hosted service state for storage, the public key is derived from the private key on load
+fn encode_hosted(h: &HostedInfo) -> Vec<u8> {
+    let mut d = Vec::new();
+
+    for f in [h.keys.pri_key.as_deref().map(|k| &k[..]), h.keys.sec_key.as_deref().map(|k| &k[..])] {
+        match f {
+            Some(v) => {
+                d.push(v.len() as u8);
+                d.extend_from_slice(v);
+            },
+            None => d.push(0),
+        }
+    }
+
+    match &h.last {
+        Some(l) => {
+            d.push(1);
+            d.extend_from_slice(&l.page_index.to_le_bytes());
+            d.extend_from_slice(&l.block_index.to_le_bytes());
+            d.extend_from_slice(&l.sig);
+        },
+        None => d.push(0),
+    }
+
+    d.extend_from_slice(&(h.subscribers.len() as u16).to_le_bytes());
+    for s in &h.subscribers {
+        d.push(s.len() as u8);
+        d.extend_from_slice(s);
+    }
+
+    d
+}
+
+/// Decode stored hosted service state
+fn decode_hosted(d: &[u8]) -> Option<HostedInfo> {
+    let mut h = HostedInfo::default();
+
+    // Read length-prefixed keys
+    let mut fields = [None, None];
+    let mut i = 0;
+    for f in fields.iter_mut() {
+        let n = *d.get(i)? as usize;
+        if n > 0 {
+            *f = Some(d.get(i+1..i+1+n)?);
+        }
+        i += 1 + n;
+    }
+
+    let pri_key = PrivateKey::try_from(fields[0]?).ok()?;
+    h.keys.pub_key = Some(Crypto::get_public(&pri_key));
+    h.keys.pri_key = Some(pri_key);
+    h.keys.sec_key = match fields[1] {
+        Some(k) => Some(SecretKey::try_from(k).ok()?),
+        None => None,
+    };
+
+    if *d.get(i)? != 0 {
+        let l = d.get(i+1..i+5+SIGNATURE_LEN)?;
+        h.last = Some(ObjectInfo{
+            page_index: LittleEndian::read_u16(&l[0..]),
+            block_index: LittleEndian::read_u16(&l[2..]),
+            sig: Signature::try_from(&l[4..]).ok()?,
+        });
+        i += 4 + SIGNATURE_LEN;
+    }
+    i += 1;
+
+    let count = LittleEndian::read_u16(d.get(i..i+2)?);
+    i += 2;
+    for _ in 0..count {
+        let n = *d.get(i)? as usize;
+        h.subscribers.push(Id::try_from(d.get(i+1..i+1+n)?).ok()?);
+        i += 1 + n;
+    }
+
+    match i == d.len() {
+        true => Some(h),
+        false => None,
+    }
+}
+
+impl <Addr: Clone + Debug + SledAddress + 'static> Store for SledStore<Addr> {
     const FEATURES: StoreFlags = StoreFlags::ALL;
 
     type Address = Addr;
@@ -112,8 +377,20 @@ impl <Addr: Clone + Debug + 'static> Store for SledStore<Addr> {
     }
 
     fn update_peer<R: Debug, F: Fn(&mut Peer<Addr>)-> R>(&mut self, id: &Id, f: F) -> Result<R, Self::Error> {
+        let new = !self.peers.contains_key(id);
         let p = self.peers.entry(id.clone()).or_default();
-        Ok(f(p))
+        let prev = encode_peer(p);
+        let r = f(p);
+
+        // Write through to storage where persisted fields have changed,
+        // runtime state (ie. last seen, online) is updated frequently and not persisted
+        let d = encode_peer(p);
+        if new || d != prev {
+            let peers = self.db.open_tree(SLED_PEER_KEY)?;
+            peers.insert(id, d)?;
+        }
+
+        Ok(r)
     }
 
     fn store_page<T: ImmutableData>(&mut self, sig: &Signature, p: &Container<T>) -> Result<(), Self::Error> {
@@ -140,6 +417,59 @@ impl <Addr: Clone + Debug + 'static> Store for SledStore<Addr> {
             None => Ok(None),
         }
     }
+
+    fn remove_page(&mut self, sig: &Signature) -> Result<(), Self::Error> {
+        let pages = self.db.open_tree(SLED_PAGE_KEY)?;
+
+        pages.remove(sig)?;
+
+        Ok(())
+    }
+
+    fn visit_pages<F: FnMut(&Signature, Container<&[u8]>)>(&self, mut f: F) -> Result<(), Self::Error> {
+        for (sig, p) in self.pages().collect::<Result<Vec<_>, _>>()? {
+            // Skip pages shorter than their header describes
+            if p.len() < HEADER_LEN + ID_LEN + SIGNATURE_LEN {
+                continue;
+            }
+            match Container::from(&p[..]) {
+                (c, n) if n <= p.len() => f(&sig, c),
+                _ => log::warn!("Skipping malformed stored page: {}", sig),
+            }
+        }
+        Ok(())
+    }
+
+    fn get_hosted(&self, id: &Id) -> Result<Option<HostedInfo>, Self::Error> {
+        let hosted = self.db.open_tree(SLED_HOSTED_KEY)?;
+
+        match hosted.get(id)? {
+            Some(v) => match decode_hosted(&v) {
+                Some(h) => Ok(Some(h)),
+                None => {
+                    log::warn!("Failed to decode stored hosted service: {}", id);
+                    Ok(None)
+                },
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn set_hosted(&mut self, id: &Id, info: &HostedInfo) -> Result<(), Self::Error> {
+        let hosted = self.db.open_tree(SLED_HOSTED_KEY)?;
+
+        hosted.insert(id, encode_hosted(info))?;
+
+        Ok(())
+    }
+
+    fn remove_hosted(&mut self, id: &Id) -> Result<(), Self::Error> {
+        let hosted = self.db.open_tree(SLED_HOSTED_KEY)?;
+
+        hosted.remove(id)?;
+
+        Ok(())
+    }
 }
 
 impl <Addr: Clone + Debug> KeySource for SledStore<Addr> {
@@ -148,6 +478,228 @@ impl <Addr: Clone + Debug> KeySource for SledStore<Addr> {
     }
 }
 
+/// Summary of [SledStore] contents for inspection, excluding private keys
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StoreDump {
+    pub id: Option<String>,
+    pub pub_key: Option<String>,
+    pub encrypted: bool,
+    pub last: Option<LastDump>,
+    pub peers: Vec<PeerDump>,
+    pub pages: Vec<PageDump>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LastDump {
+    pub page_index: u16,
+    pub block_index: u16,
+    pub sig: String,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PeerDump {
+    pub id: String,
+    pub pub_key: Option<String>,
+    pub addr: Option<String>,
+    pub subscriber: bool,
+    pub subscribed: String,
+    pub access: String,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PageDump {
+    pub sig: String,
+    pub id: String,
+    pub kind: String,
+    pub index: u16,
+    pub len: usize,
+}
+
+impl core::fmt::Display for StoreDump {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "id: {}", self.id.as_deref().unwrap_or("none"))?;
+        writeln!(f, "public key: {}", self.pub_key.as_deref().unwrap_or("none"))?;
+        writeln!(f, "encrypted: {}", self.encrypted)?;
+
+        match &self.last {
+            Some(l) => writeln!(f, "last: page {} block {} sig {}", l.page_index, l.block_index, l.sig)?,
+            None => writeln!(f, "last: none")?,
+        }
+
+        writeln!(f, "peers ({}):", self.peers.len())?;
+        for p in &self.peers {
+            writeln!(f, "  {} addr: {} subscriber: {} subscribed: {} access: {}",
+                p.id, p.addr.as_deref().unwrap_or("none"), p.subscriber, p.subscribed, p.access)?;
+        }
+
+        writeln!(f, "pages ({}):", self.pages.len())?;
+        for p in &self.pages {
+            writeln!(f, "  {} id: {} kind: {} index: {} len: {}", p.sig, p.id, p.kind, p.index, p.len)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Magic header for exported store archives
+const ARCHIVE_MAGIC: &[u8] = b"DSFSTORE";
+const ARCHIVE_VERSION: u8 = 1;
+
+/// Maximum length of an archive field, bounding allocations on import
+const ARCHIVE_FIELD_MAX: usize = 64 * 1024;
+
+impl <Addr: Clone + Debug + SledAddress + 'static> SledStore<Addr> {
+    /// Summarise store contents for inspection, private keys are not included
+    pub fn dump(&self) -> Result<StoreDump, sled::Error> {
+        let mut d = StoreDump::default();
+
+        if let Some(k) = self.get_ident()? {
+            d.pub_key = k.pub_key.as_ref().map(|k| k.to_string());
+            d.id = k.pub_key.as_ref()
+                .and_then(|k| Crypto::hash(k).ok())
+                .map(|h| Id::from(h).to_string());
+            d.encrypted = k.sec_key.is_some();
+        }
+
+        d.last = self.get_last()?.map(|l| LastDump{
+            page_index: l.page_index,
+            block_index: l.block_index,
+            sig: l.sig.to_string(),
+        });
+
+        for (id, p) in self.peers() {
+            d.peers.push(PeerDump{
+                id: id.to_string(),
+                pub_key: p.keys.pub_key.as_ref().map(|k| k.to_string()),
+                addr: p.addr.as_ref().map(|a| format!("{:?}", a)),
+                subscriber: p.subscriber,
+                subscribed: format!("{:?}", p.subscribed),
+                access: format!("{:?}", p.access),
+            });
+        }
+
+        for p in self.pages() {
+            let (sig, raw) = p?;
+            let len = raw.len();
+            let (c, _n) = Container::from(raw);
+
+            d.pages.push(PageDump{
+                sig: sig.to_string(),
+                id: c.id().to_string(),
+                kind: format!("{:?}", c.header().kind()),
+                index: c.header().index(),
+                len,
+            });
+        }
+
+        Ok(d)
+    }
+
+    /// Export the full store, including private keys, to a portable archive
+    pub fn export<W: std::io::Write>(&self, mut w: W) -> Result<(), std::io::Error> {
+        w.write_all(ARCHIVE_MAGIC)?;
+        w.write_all(&[ARCHIVE_VERSION])?;
+
+        for name in self.archive_trees() {
+            let tree = self.db.open_tree(&name).map_err(std::io::Error::from)?;
+
+            for r in tree.iter() {
+                let (k, v) = r.map_err(std::io::Error::from)?;
+
+                for f in [name.as_ref(), k.as_ref(), v.as_ref()] {
+                    w.write_all(&(f.len() as u32).to_le_bytes())?;
+                    w.write_all(f)?;
+                }
+            }
+        }
+
+        w.flush()
+    }
+
+    /// Import a store archive created with [SledStore::export], replacing existing store contents.
+    ///
+    /// The archive is read and validated in full before the store is cleared,
+    /// so a malformed archive leaves the existing contents unchanged
+    pub fn import<R: std::io::Read>(&mut self, mut r: R) -> Result<(), std::io::Error> {
+        use std::io::{Error, ErrorKind};
+
+        let mut header = [0u8; 9];
+        r.read_exact(&mut header)?;
+        if &header[..8] != ARCHIVE_MAGIC || header[8] != ARCHIVE_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, "invalid store archive"));
+        }
+
+        let mut records = Vec::new();
+
+        'records: loop {
+            let mut fields: [Vec<u8>; 3] = Default::default();
+
+            for (i, f) in fields.iter_mut().enumerate() {
+                let mut n = [0u8; 4];
+                match r.read_exact(&mut n) {
+                    Ok(_) => (),
+                    // Archive ends on a record boundary
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof && i == 0 => break 'records,
+                    Err(e) => return Err(e),
+                }
+
+                let n = u32::from_le_bytes(n) as usize;
+                if n > ARCHIVE_FIELD_MAX {
+                    return Err(Error::new(ErrorKind::InvalidData, "archive field too long"));
+                }
+
+                f.resize(n, 0);
+                r.read_exact(f)?;
+            }
+
+            let [name, k, v] = fields;
+            if !self.valid_record(&name, &k, &v) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("invalid archive record in tree {:?}", String::from_utf8_lossy(&name))));
+            }
+
+            records.push((name, k, v));
+        }
+
+        // Replace existing contents
+        for name in self.archive_trees() {
+            self.db.open_tree(name).map_err(Error::from)?
+                .clear().map_err(Error::from)?;
+        }
+
+        for (name, k, v) in records {
+            self.db.open_tree(name).map_err(Error::from)?
+                .insert(k, v).map_err(Error::from)?;
+        }
+
+        self.reload().map_err(Error::from)
+    }
+
+    /// Trees included in store archives
+    fn archive_trees(&self) -> [Vec<u8>; 5] {
+        [self.db.name().to_vec(), SLED_IDENT_KEY.to_vec(), SLED_PAGE_KEY.to_vec(), SLED_PEER_KEY.to_vec(), SLED_HOSTED_KEY.to_vec()]
+    }
+
+    /// Check an archive record decodes as the entry type stored in its tree
+    fn valid_record(&self, name: &[u8], k: &[u8], v: &[u8]) -> bool {
+        match name {
+            SLED_IDENT_KEY => match k {
+                b"pri_key" => PrivateKey::try_from(v).is_ok(),
+                b"sec_key" => SecretKey::try_from(v).is_ok(),
+                _ => false,
+            },
+            SLED_PAGE_KEY => Signature::try_from(k).is_ok() && !v.is_empty(),
+            SLED_PEER_KEY => Id::try_from(k).is_ok() && decode_peer::<Addr>(v).is_some(),
+            SLED_HOSTED_KEY => Id::try_from(k).is_ok() && decode_hosted(v).is_some(),
+            n if n == self.db.name().as_ref() => k == SLED_LAST_KEY && v.len() == 2 + 2 + SIGNATURE_LEN,
+            _ => false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{env::temp_dir, net::SocketAddr};
@@ -210,6 +762,16 @@ mod tests {
         assert_eq!(peer.subscriber, true);
         assert_eq!(peer.subscribed, SubscribeState::Subscribed);
 
+        // Re-open store and check peer is loaded
+        drop(store);
+        let store = SledStore::<SocketAddr>::new(f.path().to_str().unwrap()).unwrap();
+        let peer = store.get_peer(&id).unwrap().unwrap();
+
+        assert_eq!(peer.keys.pub_key, keys.pub_key);
+        assert_eq!(peer.keys.sec_key, keys.sec_key);
+        assert_eq!(peer.addr, Some(SocketAddr::from(([127, 0, 0, 1], 1234))));
+        assert_eq!(peer.subscriber, true);
+        assert_eq!(peer.subscribed, SubscribeState::Subscribed);
     }
 
     #[test]
@@ -253,6 +815,126 @@ mod tests {
         assert_eq!(p1.body_raw(), p.body_raw());
         assert_eq!(p1.public_options_raw(), p.public_options_raw());
         assert_eq!(p1.private_options_raw(), p.private_options_raw());
+
+        let mut visited = Vec::new();
+        store.visit_pages(|sig, c| visited.push((sig.clone(), c.id())) ).unwrap();
+        assert_eq!(visited, vec![(p.signature(), s.id())]);
+
+        store.remove_page(&p.signature()).unwrap();
+        assert!(store.fetch_page(&p.signature(), &mut buff).unwrap().is_none());
+    }
+
+    #[test]
+    fn sled_store_hosted() {
+        let f = tempdir().unwrap();
+
+        let mut store = SledStore::<SocketAddr>::new(f.path().to_str().unwrap()).unwrap();
+
+        let (pub_key, pri_key) = Crypto::new_pk().unwrap();
+        let id = Id::from(Crypto::hash(&pub_key).unwrap());
+        let sub = Id::from(Crypto::hash(&Crypto::new_pk().unwrap().0).unwrap());
+
+        let mut s = ServiceBuilder::<Vec<u8>>::generic().build().unwrap();
+        let mut buff = vec![0u8; 1024];
+        let (_n, p) = s.publish_primary(Default::default(), &mut buff).unwrap();
+
+        let info = HostedInfo{
+            keys: Keys{ pub_key: Some(pub_key), pri_key: Some(pri_key), sec_key: None, sym_keys: None },
+            last: Some(ObjectInfo{ page_index: 3, block_index: 4, sig: p.signature() }),
+            subscribers: vec![sub],
+        };
+        store.set_hosted(&id, &info).unwrap();
+
+        assert_eq!(store.get_hosted(&id).unwrap(), Some(info));
+        assert_eq!(store.hosted().unwrap(), vec![id.clone()]);
+
+        // Hosted services are not listed as peers
+        assert_eq!(store.peers().count(), 0);
+
+        store.remove_hosted(&id).unwrap();
+        assert_eq!(store.get_hosted(&id).unwrap(), None);
+    }
+
+    #[test]
+    fn sled_store_export_import() {
+        let f = tempdir().unwrap();
+        let mut store = SledStore::<SocketAddr>::new(f.path().to_str().unwrap()).unwrap();
+
+        // Populate store
+        let (pub_key, pri_key) = Crypto::new_pk().unwrap();
+        let keys = Keys{ pub_key: Some(pub_key), pri_key: Some(pri_key), sec_key: None, sym_keys: None };
+        store.set_ident(&keys).unwrap();
+
+        let id = Id::from(Crypto::hash(&keys.pub_key.clone().unwrap()).unwrap());
+        store.update_peer(&id, |p| {
+            p.addr = Some(SocketAddr::from(([127, 0, 0, 1], 1234)));
+            p.subscriber = true;
+        }).unwrap();
+
+        let mut s = ServiceBuilder::<Vec<u8>>::generic().body(vec![0xaa, 0xbb, 0xcc]).build().unwrap();
+        let mut buff = vec![0u8; 1024];
+        let (_n, p) = s.publish_primary(Default::default(), &mut buff).unwrap();
+        store.store_page(&p.signature(), &p).unwrap();
+
+        // Export and import into a new store
+        let mut archive = Vec::new();
+        store.export(&mut archive).unwrap();
+
+        let f2 = tempdir().unwrap();
+        let mut store2 = SledStore::<SocketAddr>::new(f2.path().to_str().unwrap()).unwrap();
+        store2.import(archive.as_slice()).unwrap();
+
+        // Check contents match
+        assert_eq!(store2.get_ident().unwrap(), store.get_ident().unwrap());
+        assert_eq!(store2.get_peer(&id).unwrap(), store.get_peer(&id).unwrap());
+        assert_eq!(store2.dump().unwrap(), store.dump().unwrap());
+
+        // Check dump excludes private keys
+        let d = store2.dump().unwrap();
+        assert_eq!(d.id, Some(id.to_string()));
+        assert_eq!(d.peers.len(), 1);
+        assert_eq!(d.pages.len(), 1);
+    }
+
+    #[test]
+    fn sled_store_import_replace() {
+        let f = tempdir().unwrap();
+        let mut store = SledStore::<SocketAddr>::new(f.path().to_str().unwrap()).unwrap();
+
+        let (pub_key, pri_key) = Crypto::new_pk().unwrap();
+        store.set_ident(&Keys{ pub_key: Some(pub_key), pri_key: Some(pri_key), sec_key: None, sym_keys: None }).unwrap();
+
+        let mut archive = Vec::new();
+        store.export(&mut archive).unwrap();
+
+        // Populate a second store with an identity including a secret key and a peer
+        let f2 = tempdir().unwrap();
+        let mut store2 = SledStore::<SocketAddr>::new(f2.path().to_str().unwrap()).unwrap();
+
+        let (pub_key, pri_key) = Crypto::new_pk().unwrap();
+        let sec_key = Crypto::new_sk().unwrap();
+        store2.set_ident(&Keys{ pub_key: Some(pub_key.clone()), pri_key: Some(pri_key), sec_key: Some(sec_key), sym_keys: None }).unwrap();
+
+        let id = Id::from(Crypto::hash(&pub_key).unwrap());
+        store2.update_peer(&id, |p| p.subscriber = true ).unwrap();
+
+        // Malformed archives are rejected without modifying the store
+        let mut bad = archive.clone();
+        bad.extend_from_slice(&(SLED_IDENT_KEY.len() as u32).to_le_bytes());
+        bad.extend_from_slice(SLED_IDENT_KEY);
+        bad.extend_from_slice(&7u32.to_le_bytes());
+        bad.extend_from_slice(b"pri_key");
+        bad.extend_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(store2.import(bad.as_slice()).is_err());
+        assert!(store2.get_peer(&id).unwrap().is_some());
+
+        // Imports replace existing contents
+        store2.import(archive.as_slice()).unwrap();
+
+        assert_eq!(store2.get_ident().unwrap(), store.get_ident().unwrap());
+        assert!(store2.get_peer(&id).unwrap().is_none());
+        assert_eq!(store2.peers().count(), 0);
     }
 
 }